@@ -0,0 +1,152 @@
+// Polyglot opening book builder (see src/chess_lib/polyglot.rs).
+//
+// Replays the games of one or more PGN files (see src/chess_lib/pgn.rs) and counts how often each move was played in each position
+// and how it scored, up to a fixed number of plies. Games with an unknown result ("*") are skipped.
+//
+// usage: bookgen [--max-ply N] [--min-count N] [--min-score PCT] [--out PATH] FILE.pgn...
+//
+// A move is kept if it was played at least --min-count times and scored at least --min-score percent
+// for the side playing it. Its weight is 2 * wins + draws (like Polyglot's own make-book), scaled down
+// per position to fit 16 bits, so moves that never scored anything end up with weight 0 and are left out.
+
+#[allow(dead_code, unused_imports)] // the tool only uses part of the engine
+mod chess_lib;
+use chess_lib::*;
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+struct Config {
+    max_ply: usize,
+    min_count: u32,
+    min_score: f32,
+    out: String,
+    inputs: Vec<String>,
+}
+
+impl Config {
+    fn from_args() -> Result<Self, String> {
+        let mut config = Config {
+            max_ply: 24,
+            min_count: 3,
+            min_score: 0.0,
+            out: "book.bin".to_string(),
+            inputs: Vec::new(),
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {}", name));
+            match arg.as_str() {
+                "--max-ply"   => config.max_ply = parse_arg(&arg, &value(&arg)?)?,
+                "--min-count" => config.min_count = parse_arg(&arg, &value(&arg)?)?,
+                "--min-score" => config.min_score = parse_arg(&arg, &value(&arg)?)?,
+                "--out"       => config.out = value(&arg)?,
+                other if other.starts_with("--") => return Err(format!("unknown argument '{}'", other)),
+                input => config.inputs.push(input.to_string()),
+            }
+        }
+
+        if config.inputs.is_empty() {
+            return Err("no PGN files given".to_string());
+        }
+        if config.min_count == 0 {
+            return Err("--min-count must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, name))
+}
+
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MoveStats {
+    games: u32,
+    points: u32, // half points for the side playing the move: 2 per win, 1 per draw
+}
+
+/// Adds the first `max_ply` moves of `game` to `stats`.
+fn add_game(game: &PgnGame, config: &Config, stats: &mut HashMap<(u64, u16), MoveStats>) {
+    // Half points for White
+    let white_points = match game.result.as_str() {
+        "1-0" => 2,
+        "1/2-1/2" => 1,
+        "0-1" => 0,
+        _ => return, // unfinished or unknown result, nothing to learn
+    };
+
+    let mut board = game.start.clone();
+    for m in game.moves.iter().take(config.max_ply) {
+        let entry = stats.entry((board.polyglot_key(), BookEntry::encode_move(&board, &m.mv))).or_default();
+        entry.games += 1;
+        entry.points += if board.turn == Color::White { white_points } else { 2 - white_points };
+
+        board = board.make_move(&m.mv);
+    }
+}
+
+fn main() {
+    let config = match Config::from_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("bookgen: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut stats: HashMap<(u64, u16), MoveStats> = HashMap::new();
+    let (mut games_read, mut games_failed) = (0, 0);
+    for input in &config.inputs {
+        let text = match std::fs::read(input) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                eprintln!("bookgen: could not read {}: {}", input, e);
+                std::process::exit(1);
+            }
+        };
+        for game in PgnReader::new(&text) {
+            match game {
+                Ok(game) => {
+                    games_read += 1;
+                    add_game(&game, &config, &mut stats);
+                }
+                Err(e) => {
+                    eprintln!("bookgen: {}: {}", input, e);
+                    games_failed += 1;
+                }
+            }
+        }
+    }
+
+    // Group by position so the weights of one position can be scaled together
+    let mut positions: HashMap<u64, Vec<(u16, MoveStats)>> = HashMap::new();
+    for ((key, mv), s) in stats {
+        let score = 50.0 * s.points as f32 / s.games as f32;
+        if s.games >= config.min_count && score >= config.min_score {
+            positions.entry(key).or_default().push((mv, s));
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (key, moves) in &positions {
+        let max_points = moves.iter().map(|(_, s)| s.points).max().unwrap_or(0);
+        let scale = (max_points as f64 / u16::MAX as f64).max(1.0);
+        for (mv, s) in moves {
+            let weight = (s.points as f64 / scale) as u16;
+            if weight > 0 {
+                entries.push(BookEntry { key: *key, mv: *mv, weight, learn: 0 });
+            }
+        }
+    }
+
+    let book = PolyglotBook::from_entries(entries);
+    if let Err(e) = std::fs::write(&config.out, book.to_bytes()) {
+        eprintln!("bookgen: could not write {}: {}", config.out, e);
+        std::process::exit(1);
+    }
+    eprintln!("bookgen: {} games ({} skipped because of errors), {} positions, {} entries -> {}",
+              games_read, games_failed, positions.len(), book.len(), config.out);
+}
@@ -7,6 +7,12 @@
 pub mod uci_parser;
 pub use uci_parser::*;
 
+pub mod xboard_parser;
+pub use xboard_parser::*;
+
+pub mod uci_options;
+pub use uci_options::*;
+
 pub mod bitboard;
 pub use bitboard::*;
 
@@ -22,9 +28,52 @@ pub use terminal_states::*;
 pub mod simple_pst;
 pub use simple_pst::*;
 
+pub mod eval_params;
+pub use eval_params::*;
+
+pub mod endgame;
+pub use endgame::*;
+
+pub mod kpk;
+pub use kpk::*;
+
+pub mod syzygy;
+pub use syzygy::*;
+
+pub mod byte_reader;
+
+pub mod dtm;
+pub use dtm::*;
+
+pub mod polyglot_keys;
+pub use polyglot_keys::*;
+
+pub mod polyglot;
+pub use polyglot::*;
+
+pub mod pgn;
+pub use pgn::*;
+
+pub mod game_tree;
+pub use game_tree::*;
+
+pub mod epd;
+pub use epd::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bench;
+#[cfg(not(target_arch = "wasm32"))]
+pub use bench::*;
+
 pub mod evaluate;
 pub use evaluate::*;
 
+pub mod nnue;
+pub use nnue::*;
+
+pub mod time_manager;
+pub use time_manager::*;
+
 pub mod search;
 pub use search::*;
 
@@ -0,0 +1,74 @@
+// Fixed-depth benchmark over built-in positions
+//
+// Every position is searched from a fresh game (empty transposition table) on the calling thread, so the
+// total node count only changes when the search or evaluation does. Quote it as `bench: <nodes>` in commit
+// messages that are not meant to change the search. Native only, `Instant` is not available in the browser.
+//
+// The bench ignores what was loaded into the engine: it searches with the handcrafted evaluation, without
+// tablebases, and with the default evaluation parameters (its own copy, the loaded set is left alone).
+
+use crate::board::*;
+use crate::eval_params::*;
+use crate::game::*;
+use crate::kpk::*;
+use crate::send_response;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Instant;
+
+pub const BENCH_DEPTH: u8 = 4; // a few seconds in release builds
+
+// Openings, middlegames with tactics and a few endgames (including KPK and a pawnless one)
+const BENCH_POSITIONS: [&str; 16] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3",
+    "rnbqkb1r/pp1p1ppp/4pn2/2p5/2PP4/2N5/PP2PPPP/R1BQKBNR w KQkq - 0 4",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1BBPPP/R2QK2R w KQ - 1 8",
+    "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+    "r1b2rk1/2q1b1pp/p2ppn2/1p6/3QP3/1BN1B3/PPP3PP/R4RK1 w - - 0 1",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+    "3b4/5kp1/1p1p1p1p/pP1PpP1P/P1P1P3/3KN3/8/8 w - - 0 1",
+    "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1",
+    "8/3k4/8/8/8/4P3/4K3/8 w - - 0 1",
+    "8/8/8/4k3/8/8/2KQ4/8 w - - 0 1",
+    "5k2/7R/4P2p/5K2/p1r2P1p/8/8/8 b - - 0 1",
+    "rnbqkb1r/ppp1pppp/5n2/3p4/3P1B2/5N2/PPP1PPPP/RN1QKB1R b KQkq - 3 3",
+];
+
+/// Searches every bench position to `depth` and prints the total nodes, time and nodes per second.
+/// Returns the total node count.
+pub fn run_bench(depth: u8) -> u64 {
+    init_kpk_bitbase(); // not part of the measured time
+
+    let default_params = Arc::new(EvalParams::default());
+    if *eval_params() != *default_params {
+        send_response!("info string bench uses the default evaluation parameters, not the loaded ones");
+    }
+
+    let mut total_nodes = 0;
+    let started = Instant::now();
+    for (i, fen) in BENCH_POSITIONS.iter().enumerate() {
+        let fen = fen.parse::<Fen>().expect("bench positions are valid");
+        let board = Board::from_fen(&fen).expect("bench positions are valid");
+        let mut game = Game::new(board, depth, 3, Arc::new(AtomicBool::new(false)));
+        game.use_tablebases = false;
+        game.use_loaded_eval_params = false;
+        game.eval_params = default_params.clone();
+
+        let best_move = game.find_best_move(depth);
+        send_response!("info string position {}/{} bestmove {} nodes {}", i + 1, BENCH_POSITIONS.len(),
+                       best_move.map_or("(none)".to_string(), |mv| mv.to_uci()), game.nodes);
+        total_nodes += game.nodes;
+    }
+    let elapsed_ms = started.elapsed().as_millis().max(1) as u64;
+
+    send_response!("===========================");
+    send_response!("Total time (ms) : {}", elapsed_ms);
+    send_response!("Nodes searched  : {}", total_nodes);
+    send_response!("Nodes/second    : {}", total_nodes * 1000 / elapsed_ms);
+    total_nodes
+}
@@ -94,6 +94,20 @@ impl Square {
         format!("{}{}", file_char, rank_char)
     }
 
+    /// Chebyshev (king move) distance between two squares.
+    #[inline]
+    pub fn distance(self, other: Square) -> u8 {
+        let file_distance = (self.file() as i8 - other.file() as i8).unsigned_abs();
+        let rank_distance = (self.rank() as i8 - other.rank() as i8).unsigned_abs();
+        file_distance.max(rank_distance)
+    }
+
+    /// Distance to the nearest edge of the board (0 on the edge, 3 in the center).
+    #[inline]
+    pub fn edge_distance(self) -> u8 {
+        self.file().min(7 - self.file()).min(self.rank()).min(7 - self.rank())
+    }
+
     pub fn is_light(&self) -> bool {
         // (self.file() + self.rank()) % 2 != 0
         ((self.to_u8() >> 3) & 1) ^ (self.to_u8() & 1) == 1
@@ -233,13 +233,26 @@ pub enum ParseMoveError {
     InvalidFormat,
     InvalidSquare,
     InvalidPromotionPiece,
+    IllegalMove, // well formed, but not legal in the position (see `Board::parse_uci_move`)
 }
 
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseMoveError::InvalidFormat => write!(f, "Invalid move format (expected e.g. e2e4 or a7a8q)"),
+            ParseMoveError::InvalidSquare => write!(f, "Invalid square in move"),
+            ParseMoveError::InvalidPromotionPiece => write!(f, "Invalid promotion piece in move"),
+            ParseMoveError::IllegalMove => write!(f, "Illegal move"),
+        }
+    }
+}
+impl std::error::Error for ParseMoveError {}
+
 /// Parses a move string in algebraic notation (e.g., "e2e4", "a7a8q").
 /// Returns a ChessMove or a ParseMoveError if the string is invalid.
 pub fn parse_move_string(move_str: &str) -> Result<ChessMove, ParseMoveError> {
     // A move string should be 4 or 5 characters long
-    if move_str.len() != 4 && move_str.len() != 5 {
+    if !move_str.is_ascii() || (move_str.len() != 4 && move_str.len() != 5) {
         return Err(ParseMoveError::InvalidFormat);
     }
 
@@ -589,9 +602,86 @@ impl Board {
             return Err(FenParseError::InvalidFullmoveNumber("Fullmove number is 0".parse::<i32>().unwrap_err()));
         }
 
+        board.validate()?;
         Ok(board)
     }
 
+    /// Rejects positions the move generator can not handle: missing kings, pawns on the first or last rank,
+    /// the side not to move in check and castling or en passant rights that do not match the pieces.
+    fn validate(&self) -> Result<(), FenParseError> {
+        let illegal = |reason: String| Err(FenParseError::IllegalPosition(reason));
+        let pieces = |piece_type: PieceType, color: Color| self.piece_bbs[piece_type as usize] & self.color_bbs[color as usize];
+
+        for color in [Color::White, Color::Black] {
+            if pieces(PieceType::King, color).popcount() != 1 {
+                return illegal(format!("{:?} must have exactly one king", color));
+            }
+        }
+        for file in 0..8 {
+            for rank in [0, 7] {
+                if self.piece_bbs[PieceType::Pawn as usize].is_set(Square::from_file_rank(file, rank)) {
+                    return illegal("pawn on the first or last rank".to_string());
+                }
+            }
+        }
+        let opponent = self.turn.opponent();
+        if self.find_king_square(opponent).is_some_and(|sq| self.is_square_attacked(sq, self.turn)) {
+            return illegal(format!("{:?} is in check but it is not their move", opponent));
+        }
+
+        let castling = [
+            (CastlingRights::WHITE_KINGSIDE, Color::White, 0, 7, 'K'),
+            (CastlingRights::WHITE_QUEENSIDE, Color::White, 0, 0, 'Q'),
+            (CastlingRights::BLACK_KINGSIDE, Color::Black, 7, 7, 'k'),
+            (CastlingRights::BLACK_QUEENSIDE, Color::Black, 7, 0, 'q'),
+        ];
+        for (right, color, rank, rook_file, symbol) in castling {
+            if self.castling_rights.has_right(right)
+                && !(pieces(PieceType::King, color).is_set(Square::from_file_rank(4, rank))
+                     && pieces(PieceType::Rook, color).is_set(Square::from_file_rank(rook_file, rank))) {
+                return illegal(format!("castling right {} without the king and rook on their squares", symbol));
+            }
+        }
+
+        if let Some(ep) = self.en_passant_square {
+            // The pawn that just moved two squares stands in front of the target square
+            let (ep_rank, pawn_rank) = if self.turn == Color::White { (5, 4) } else { (2, 3) };
+            if ep.rank() != ep_rank || !pieces(PieceType::Pawn, opponent).is_set(Square::from_file_rank(ep.file(), pawn_rank)) {
+                return illegal(format!("en passant square {} does not follow a double pawn push", ep.to_algebraic()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The same position with the colors swapped and the board flipped vertically. The evaluation and the search
+    /// should give the mirrored position the negated score, which makes it useful for finding asymmetry bugs.
+    pub fn mirrored(&self) -> Board {
+        let flip = |bb: Bitboard| Bitboard(bb.0.swap_bytes()); // reverses the ranks
+        let mut castling_rights = CastlingRights::NONE;
+        let swapped_rights = [
+            (CastlingRights::WHITE_KINGSIDE, CastlingRights::BLACK_KINGSIDE),
+            (CastlingRights::WHITE_QUEENSIDE, CastlingRights::BLACK_QUEENSIDE),
+            (CastlingRights::BLACK_KINGSIDE, CastlingRights::WHITE_KINGSIDE),
+            (CastlingRights::BLACK_QUEENSIDE, CastlingRights::WHITE_QUEENSIDE),
+        ];
+        for (right, mirrored_right) in swapped_rights {
+            if self.castling_rights.has_right(right) {
+                castling_rights.add_right(mirrored_right);
+            }
+        }
+
+        Board {
+            piece_bbs: self.piece_bbs.map(flip),
+            color_bbs: [flip(self.color_bbs[Color::Black as usize]), flip(self.color_bbs[Color::White as usize])],
+            occupied_bb: flip(self.occupied_bb),
+            turn: self.turn.opponent(),
+            castling_rights,
+            en_passant_square: self.en_passant_square.map(|sq| Square::from_file_rank(sq.file(), 7 - sq.rank())),
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+        }
+    }
+
 
     pub fn new_start_pos_from_fen() -> Self {
         let start_fen = Fen {
@@ -607,6 +697,8 @@ impl Board {
 }
 
 
+/// Plain-text diagram, e.g. for the UCI `d` command. The alternate form (`{:#}`) colours the pieces with ANSI escapes
+/// for a terminal.
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for rank in (0..8).rev() { // Iterate ranks from 8 down to 1
@@ -630,8 +722,8 @@ impl fmt::Display for Board {
                             (PieceType::King, Color::Black) => 'k',
                             (PieceType::Pawn, Color::Black) => 'p',
                         };
-                        // write!(f, "{} ", piece_char)?;
                         match color {
+                            _ if !f.alternate() => write!(f, "{} ", piece_char)?,
                             Color::Black => write!(f, "\x1b[1;34m{}\x1b[0m ", piece_char)?,
                             Color::White => write!(f, "\x1b[1;31m{}\x1b[0m ", piece_char)?,
                         }
@@ -869,6 +961,17 @@ impl Board {
     pub fn is_check(&self) -> bool {
         self.find_king_square(self.turn).map_or(false, |sq| self.is_square_attacked(sq, self.turn.opponent()))
     }
+
+    /// Parses a move in UCI notation and returns the matching legal move, with its flags set.
+    pub fn parse_uci_move(&self, move_str: &str) -> Result<ChessMove, ParseMoveError> {
+        let mv = parse_move_string(move_str)?;
+        let mut pseudo_legal_moves = Vec::with_capacity(200);
+        let mut legal_moves = Vec::with_capacity(100);
+        self.generate_legal_moves(&mut pseudo_legal_moves, &mut legal_moves);
+        legal_moves.into_iter()
+            .find(|m| m.from() == mv.from() && m.to() == mv.to() && m.promotion() == mv.promotion())
+            .ok_or(ParseMoveError::IllegalMove)
+    }
 }
 
 impl Board {
@@ -900,21 +1003,22 @@ impl Board {
         board.generate_legal_moves(pseudo_legal_moves, legal_moves);
 
         // Disambiguation
-        let ambiguous = pseudo_legal_moves
-            .into_iter()
-            .filter(|m| m.to() == mv.to() && *m != mv && board.piece_type_on_square(m.from()) == Some(piece))
+        let ambiguous = legal_moves
+            .iter()
+            .filter(|m| m.to() == mv.to() && m.from() != mv.from() && board.piece_type_on_square(m.from()) == Some(piece))
             .collect::<Vec<_>>();
-        if !ambiguous.is_empty() {
+        if piece != PieceType::Pawn && !ambiguous.is_empty() {
             let from_file = from.file();
             let from_rank = from.rank();
 
-            let file_needed = ambiguous.iter().any(|m| m.from().file() != from_file);
-            let rank_needed = ambiguous.iter().any(|m| m.from().rank() != from_rank);
+            // The file if it is unique, else the rank if that is unique, else both
+            let file_unique = ambiguous.iter().all(|m| m.from().file() != from_file);
+            let rank_unique = ambiguous.iter().all(|m| m.from().rank() != from_rank);
 
-            if file_needed {
+            if file_unique || !rank_unique {
                 san.push((b'a' + from_file) as char);
             }
-            if rank_needed || !file_needed {
+            if !file_unique {
                 san.push((b'1' + from_rank) as char);
             }
         }
@@ -954,3 +1058,312 @@ impl Board {
         san
     }
 }
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    Empty,
+    InvalidFormat(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Empty => write!(f, "Empty move"),
+            SanError::InvalidFormat(san) => write!(f, "Not a SAN move: {}", san),
+            SanError::IllegalMove(san) => write!(f, "Illegal move: {}", san),
+            SanError::AmbiguousMove(san) => write!(f, "Ambiguous move: {}", san),
+        }
+    }
+}
+impl std::error::Error for SanError {}
+
+impl Board {
+    /// Parses a move in standard algebraic notation (e.g. "Nf3", "exd5", "O-O", "e8=Q+") against the legal moves.
+    /// Also accepts `0-0`, an explicit pawn letter ("Pe4"), promotions without `=` ("e8Q"), missing or superfluous `x` and disambiguation,
+    /// and trailing `+`, `#`, `!` and `?`.
+    pub fn parse_san(&self, san: &str) -> Result<ChessMove, SanError> {
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        if text.is_empty() {
+            return Err(SanError::Empty);
+        }
+        let invalid = || SanError::InvalidFormat(san.to_string());
+        let illegal = || SanError::IllegalMove(san.to_string());
+
+        let mut pseudo_legal_moves = Vec::with_capacity(200);
+        let mut legal_moves = Vec::with_capacity(100);
+        self.generate_legal_moves(&mut pseudo_legal_moves, &mut legal_moves);
+
+        let castling = text.replace('0', "O");
+        if castling == "O-O" || castling == "O-O-O" {
+            let to_file = if castling == "O-O" { 6 } else { 2 };
+            return legal_moves.into_iter()
+                .find(|mv| self.piece_type_on_square(mv.from()) == Some(PieceType::King) && mv.from().file() == 4 && mv.to().file() == to_file)
+                .ok_or_else(illegal);
+        }
+
+        let piece_from_char = |c: char| match c {
+            'N' => Some(PieceType::Knight),
+            'B' => Some(PieceType::Bishop),
+            'R' => Some(PieceType::Rook),
+            'Q' => Some(PieceType::Queen),
+            'K' => Some(PieceType::King),
+            _ => None,
+        };
+
+        let mut chars: Vec<char> = text.chars().filter(|&c| c != 'x' && c != ':' && c != '-').collect();
+
+        // Promotion: "=Q" or just a piece letter after the destination square
+        let mut promotion = None;
+        if chars.len() >= 3 {
+            let (before, last) = (chars[chars.len() - 2], chars[chars.len() - 1]);
+            if before == '=' || (before.is_ascii_digit() && last.is_ascii_alphabetic()) {
+                let piece = piece_from_char(last.to_ascii_uppercase()).filter(|&p| p != PieceType::King).ok_or_else(invalid)?;
+                promotion = Some(piece);
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+
+        let piece = match chars.first().and_then(|&c| if c == 'P' { Some(PieceType::Pawn) } else { piece_from_char(c) }) {
+            Some(piece) => {
+                chars.remove(0);
+                piece
+            }
+            None => PieceType::Pawn,
+        };
+
+        if chars.len() < 2 || chars.len() > 4 {
+            return Err(invalid());
+        }
+        let to_text: String = chars[chars.len() - 2..].iter().collect();
+        let to = Square::from_algebraic(&to_text).ok_or_else(invalid)?;
+
+        let mut from_file = None;
+        let mut from_rank = None;
+        for &c in &chars[..chars.len() - 2] {
+            match c {
+                'a'..='h' if from_file.is_none() => from_file = Some(c as u8 - b'a'),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c as u8 - b'1'),
+                _ => return Err(invalid()),
+            }
+        }
+
+        let mut candidates = legal_moves.into_iter().filter(|mv| {
+            mv.to() == to
+                && mv.promotion() == promotion
+                && self.piece_type_on_square(mv.from()) == Some(piece)
+                && from_file.is_none_or(|file| mv.from().file() == file)
+                && from_rank.is_none_or(|rank| mv.from().rank() == rank)
+        });
+        let mv = candidates.next().ok_or_else(illegal)?;
+        if candidates.next().is_some() {
+            return Err(SanError::AmbiguousMove(san.to_string()));
+        }
+        Ok(mv)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval_params::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(&fen.parse::<Fen>().unwrap()).unwrap()
+    }
+
+    fn san(board: &Board, san: &str) -> Result<String, SanError> {
+        board.parse_san(san).map(|mv| mv.to_uci())
+    }
+
+    #[test]
+    fn parses_pawn_and_piece_moves() {
+        let start = Board::new_start_pos();
+        assert_eq!(san(&start, "e4"), Ok("e2e4".to_string()));
+        assert_eq!(san(&start, "Pe4"), Ok("e2e4".to_string()));
+        assert_eq!(san(&start, "Nf3"), Ok("g1f3".to_string()));
+        assert_eq!(san(&start, "Ng1-f3"), Ok("g1f3".to_string()));
+
+        let open = board("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2");
+        assert_eq!(san(&open, "exd5"), Ok("e4d5".to_string()));
+        assert_eq!(san(&open, "ed5"), Ok("e4d5".to_string()));
+        assert_eq!(san(&open, "Bb5+"), Ok("f1b5".to_string()));
+    }
+
+    #[test]
+    fn parses_castling() {
+        let white = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(san(&white, "O-O"), Ok("e1g1".to_string()));
+        assert_eq!(san(&white, "O-O-O"), Ok("e1c1".to_string()));
+        assert_eq!(san(&white, "0-0"), Ok("e1g1".to_string()));
+
+        let black = board("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1");
+        assert_eq!(san(&black, "O-O+"), Ok("e8g8".to_string()));
+        assert_eq!(san(&black, "0-0-0"), Ok("e8c8".to_string()));
+
+        let no_rights = board("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1");
+        assert_eq!(san(&no_rights, "O-O"), Err(SanError::IllegalMove("O-O".to_string())));
+    }
+
+    #[test]
+    fn parses_promotions_with_and_without_equals_sign() {
+        let promotion = board("3r4/4P3/8/8/8/8/k7/4K3 w - - 0 1");
+        assert_eq!(san(&promotion, "e8=Q"), Ok("e7e8q".to_string()));
+        assert_eq!(san(&promotion, "e8Q"), Ok("e7e8q".to_string()));
+        assert_eq!(san(&promotion, "e8=N+"), Ok("e7e8n".to_string()));
+        assert_eq!(san(&promotion, "exd8=R"), Ok("e7d8r".to_string()));
+        assert_eq!(san(&promotion, "exd8B"), Ok("e7d8b".to_string()));
+        assert_eq!(san(&promotion, "e8"), Err(SanError::IllegalMove("e8".to_string())));
+        assert_eq!(san(&promotion, "e8=K"), Err(SanError::InvalidFormat("e8=K".to_string())));
+    }
+
+    #[test]
+    fn resolves_disambiguation_and_rejects_ambiguous_moves() {
+        let knights = board("4k3/8/8/8/8/8/8/1N1K1N2 w - - 0 1");
+        assert_eq!(san(&knights, "Nbd2"), Ok("b1d2".to_string()));
+        assert_eq!(san(&knights, "Nfd2"), Ok("f1d2".to_string()));
+        assert_eq!(san(&knights, "Nf1d2"), Ok("f1d2".to_string()));
+        assert_eq!(san(&knights, "Nd2"), Err(SanError::AmbiguousMove("Nd2".to_string())));
+
+        let rooks = board("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(san(&rooks, "R1a3"), Ok("a1a3".to_string()));
+        assert_eq!(san(&rooks, "R5a3"), Ok("a5a3".to_string()));
+        assert_eq!(san(&rooks, "Ra3"), Err(SanError::AmbiguousMove("Ra3".to_string())));
+    }
+
+    #[test]
+    fn rejects_illegal_and_malformed_moves() {
+        let start = Board::new_start_pos();
+        assert_eq!(san(&start, "e5"), Err(SanError::IllegalMove("e5".to_string())));
+        assert_eq!(san(&start, "Ke2"), Err(SanError::IllegalMove("Ke2".to_string())));
+        assert_eq!(san(&start, "Nc6"), Err(SanError::IllegalMove("Nc6".to_string())));
+        assert_eq!(san(&start, "Zz9"), Err(SanError::InvalidFormat("Zz9".to_string())));
+        assert_eq!(san(&start, ""), Err(SanError::Empty));
+
+        // The knight on e2 is pinned against the king
+        let pinned = board("4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1");
+        assert_eq!(san(&pinned, "Nc3"), Err(SanError::IllegalMove("Nc3".to_string())));
+    }
+
+    #[test]
+    fn accepts_reachable_positions() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 2",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+            "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1",
+            "4k3/8/8/8/8/8/8/4K2r w - - 0 1", // the side to move may be in check
+        ] {
+            let fen = fen.parse::<Fen>().unwrap();
+            assert!(Board::from_fen(&fen).is_ok(), "{}", fen.to_string());
+        }
+    }
+
+    #[test]
+    fn rejects_unreachable_positions() {
+        let illegal = |fen: &str| matches!(Board::from_fen(&fen.parse::<Fen>().unwrap()), Err(FenParseError::IllegalPosition(_)));
+        // stale castling rights
+        assert!(illegal("r3k2r/8/8/8/8/8/8/R3K1R1 w KQkq - 0 1"));
+        assert!(illegal("r3k2r/8/8/8/8/8/8/R2K3R w KQkq - 0 1"));
+        assert!(illegal("1r2k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        // en passant squares that do not follow a double push
+        assert!(illegal("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1"));
+        assert!(illegal("rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq e3 0 2"));
+        assert!(illegal("rnbqkbnr/pppp1ppp/8/8/4p3/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 2"));
+        assert!(illegal("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d3 0 2"));
+        // missing kings, pawns on the back ranks, the side not to move in check
+        assert!(illegal("8/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(illegal("4k3/8/8/8/8/8/8/4KK2 w - - 0 1"));
+        assert!(illegal("4k2P/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(illegal("4k3/8/8/8/8/8/8/p3K3 w - - 0 1"));
+        assert!(illegal("4k2R/8/8/8/8/8/8/4K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn rejects_illegal_uci_moves() {
+        let start = Board::new_start_pos();
+        assert!(start.parse_uci_move("e2e4").is_ok());
+        for mv in ["e2e5", "e1g1", "g1g3", "e7e5", "a2a1q", "e2", "i2i4"] {
+            assert!(start.parse_uci_move(mv).is_err(), "{}", mv);
+        }
+
+        let pinned = board("4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1");
+        assert!(pinned.parse_uci_move("e2c3").is_err());
+        let promotion = board("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        assert!(promotion.parse_uci_move("a7a8q").is_ok());
+        assert!(promotion.parse_uci_move("a7a8k").is_err());
+    }
+
+    const MIRROR_TEST_FENS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+        "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b Kkq - 3 3",
+        "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+        "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1",
+    ];
+
+    #[test]
+    fn mirroring_swaps_the_colours_and_flips_the_board() {
+        let start = Board::new_start_pos();
+        assert_eq!(start.mirrored().to_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+
+        let mirrored = board("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w Kq d6 0 2").mirrored();
+        assert_eq!(mirrored.to_fen(), "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b Qk d3 0 2");
+        assert_eq!(mirrored.compute_zobrist_hash(), board(&mirrored.to_fen()).compute_zobrist_hash());
+    }
+
+    #[test]
+    fn mirroring_twice_gives_the_same_position() {
+        for fen in MIRROR_TEST_FENS {
+            let board = board(fen);
+            assert_eq!(board.mirrored().mirrored(), board, "{}", fen);
+            assert_ne!(board.mirrored(), board, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn mirrored_positions_evaluate_to_the_negated_score() {
+        let params = EvalParams::default();
+        let evaluate = |board: &Board| {
+            let mut legal_moves = Vec::new();
+            board.generate_legal_moves(&mut Vec::new(), &mut legal_moves);
+            board.evaluate(0, &mut Vec::new(), &mut legal_moves, GameState::Ongoing, &params)
+        };
+        for fen in MIRROR_TEST_FENS {
+            let board = board(fen);
+            assert_eq!(evaluate(&board.mirrored()), -evaluate(&board), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn finds_the_pieces_giving_check() {
+        let checkers = |fen: &str| -> Vec<String> { board(fen).checkers().iter().map(|sq| sq.to_algebraic()).collect() };
+        assert!(checkers("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_empty());
+        assert_eq!(checkers("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"), ["h4"]);
+        assert_eq!(checkers("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1"), ["d2"]);
+        assert_eq!(checkers("4k3/8/8/8/8/5n2/8/4K3 w - - 0 1"), ["f3"]);
+        // double check by a knight and a rook
+        assert_eq!(checkers("4r1k1/8/8/8/8/3n4/8/4K3 w - - 0 1"), ["d3", "e8"]);
+        // a piece of the side to move does not check its own king
+        assert!(checkers("4k3/8/8/8/8/8/3P4/4K3 w - - 0 1").is_empty());
+        assert!(checkers("4k3/8/8/8/8/8/8/4K2R w - - 0 1").is_empty());
+        assert_eq!(board("4k3/8/8/8/8/3n4/8/4K3 w - - 0 1").mirrored().checkers().iter().map(|sq| sq.to_algebraic()).collect::<Vec<_>>(), ["d6"]);
+    }
+
+    #[test]
+    fn displays_a_plain_diagram_unless_alternate() {
+        let start = Board::new_start_pos();
+        let plain = start.to_string();
+        assert!(!plain.contains('\x1b'));
+        assert!(plain.starts_with("8 r n b q k b n r \n7 p p p p p p p p \n6 . . . . . . . . \n"));
+        assert!(plain.contains("1 R N B Q K B N R \n  a b c d e f g h\n"));
+        assert!(format!("{:#}", start).contains("\x1b[1;31mK\x1b[0m "));
+    }
+}
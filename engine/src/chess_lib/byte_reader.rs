@@ -0,0 +1,73 @@
+// Little-endian reader for the engine's binary files (NNUE networks, DTM tables)
+//
+// Each format maps `UnexpectedEof` onto its own error type, so `?` reports a truncated file in that format's terms.
+
+/// The data ended before the value being read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnexpectedEof;
+
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        ByteReader { bytes, pos: 0 }
+    }
+
+    /// Bytes not read yet.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], UnexpectedEof> {
+        let slice = self.bytes.get(self.pos..self.pos + n).ok_or(UnexpectedEof)?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, UnexpectedEof> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, UnexpectedEof> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, UnexpectedEof> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i16_vec(&mut self, len: usize) -> Result<Vec<i16>, UnexpectedEof> {
+        let raw = self.take(len * 2)?;
+        Ok(raw.chunks_exact(2).map(|c| i16::from_le_bytes([c[0], c[1]])).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_little_endian_values_in_order() {
+        let bytes = [b'C', b'F', 7, 1, 0, 0, 0, 0xfe, 0xff, 0xff, 0xff, 0x01, 0x02, 0xff, 0xff];
+        let mut reader = ByteReader::new(&bytes);
+        assert_eq!(reader.take(2), Ok(&b"CF"[..]));
+        assert_eq!(reader.u8(), Ok(7));
+        assert_eq!(reader.u32(), Ok(1));
+        assert_eq!(reader.i32(), Ok(-2));
+        assert_eq!(reader.i16_vec(2), Ok(vec![0x0201, -1]));
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_data() {
+        let mut reader = ByteReader::new(&[1, 2, 3]);
+        assert_eq!(reader.u32(), Err(UnexpectedEof));
+        assert_eq!(reader.remaining(), 3);
+        assert_eq!(reader.i16_vec(2), Err(UnexpectedEof));
+        assert_eq!(reader.take(3), Ok(&[1, 2, 3][..]));
+        assert_eq!(reader.u8(), Err(UnexpectedEof));
+    }
+}
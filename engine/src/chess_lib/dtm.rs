@@ -0,0 +1,386 @@
+// Exact distance-to-mate tables for small pawnless endgames (KQK, KRK, KBNK, KQKR, ...), generated by retrograde analysis
+// see: https://www.chessprogramming.org/Retrograde_Analysis
+//
+// One byte per position: 0 = draw, otherwise the distance to mate in plies + 1.
+// Odd distances are wins for the side to move, even ones (0 = checkmated) are losses.
+//
+// Without pawns every one of the 8 board symmetries keeps the value of a position, so positions are first mapped
+// so that the white king is in the a1-d1-d4 triangle (and, with the white king on the diagonal, the first piece
+// off the diagonal is below it). Pieces are ordered white king, black king, then White's and Black's other pieces
+// from queen to knight, and
+//   index = ((black to move * 10 + white king triangle index) * 64 + black king) * 64 + ... for every other piece
+// White is always the side with more material, positions with the colours the other way round are mirrored.
+//
+// The tables are generated by `tbgen` (src/dtm_generator.rs), the engine only loads and probes them.
+//
+// File format (.cftb, all little-endian):
+//   magic "CFTB", u32 version, u8 name length, name (e.g. "KQvKR"), u32 entry count
+//   then blocks covering every entry: a header byte n < 128 followed by n + 1 literal values,
+//   or n >= 128 followed by one value repeated n - 126 times
+
+use crate::board::*;
+use crate::game::*;
+use crate::endgame::*;
+use crate::syzygy::*;
+use crate::byte_reader::*;
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::fmt;
+
+pub const DTM_MAX_PIECES: usize = 4;
+pub(crate) const DTM_MAGIC: &[u8; 4] = b"CFTB";
+pub(crate) const DTM_VERSION: u32 = 1;
+
+pub(crate) const DTM_DRAW: u8 = 0;
+pub(crate) const DTM_INVALID: u8 = u8::MAX; // only used while generating
+pub(crate) const DTM_MAX_PLIES: usize = DTM_INVALID as usize - 2;
+
+const NON_KING_PIECES: [PieceType; 4] = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
+
+// Squares of the a1-d1-d4 triangle, and the reverse mapping (-1 outside of it)
+pub(crate) const TRIANGLE_SQUARES: [u8; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+const TRIANGLE_INDEX: [i8; 64] = {
+    let mut table = [-1i8; 64];
+    let mut i = 0;
+    while i < TRIANGLE_SQUARES.len() {
+        table[TRIANGLE_SQUARES[i] as usize] = i as i8;
+        i += 1;
+    }
+    table
+};
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DtmError {
+    Io(String),
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    InvalidMaterial(String),
+    UnexpectedEof,
+    SizeMismatch { expected: usize, found: usize },
+    TrailingBytes(usize),
+    MissingSubtable(String),
+}
+
+impl fmt::Display for DtmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DtmError::Io(e) => write!(f, "Could not access DTM table: {}", e),
+            DtmError::InvalidMagic => write!(f, "Not a ChessFlow DTM table"),
+            DtmError::UnsupportedVersion(v) => write!(f, "Unsupported DTM table version {}", v),
+            DtmError::InvalidMaterial(m) => write!(f, "Invalid DTM material '{}' (pawnless, at most {} pieces, e.g. KQvKR)", m, DTM_MAX_PIECES),
+            DtmError::UnexpectedEof => write!(f, "DTM table is truncated"),
+            DtmError::SizeMismatch { expected, found } => write!(f, "DTM table has {} entries, expected {}", found, expected),
+            DtmError::TrailingBytes(n) => write!(f, "DTM table has {} unexpected trailing bytes", n),
+            DtmError::MissingSubtable(m) => write!(f, "DTM table {} is needed first", m),
+        }
+    }
+}
+impl std::error::Error for DtmError {}
+
+impl From<UnexpectedEof> for DtmError {
+    fn from(_: UnexpectedEof) -> Self {
+        DtmError::UnexpectedEof
+    }
+}
+
+
+/// Which pieces a table holds and how positions are indexed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtmLayout {
+    pub signature: MaterialSignature,
+    pub(crate) pieces: Vec<(PieceType, Color)>,
+}
+
+impl DtmLayout {
+    /// Layout for a material such as "KQvKR", with the colours swapped if Black is the stronger side.
+    pub fn from_name(name: &str) -> Result<Self, DtmError> {
+        let invalid = || DtmError::InvalidMaterial(name.to_string());
+
+        let (white, black) = name.split_once('v').ok_or_else(invalid)?;
+        let mut counts = [[0u8; 6]; 2];
+        for (color, side) in [(Color::White, white), (Color::Black, black)] {
+            if !side.starts_with('K') {
+                return Err(invalid());
+            }
+            for c in side[1..].chars() {
+                let piece_type = NON_KING_PIECES.into_iter().find(|pt| pt.to_char() == c.to_ascii_uppercase()).ok_or_else(invalid)?;
+                counts[color as usize][piece_type as usize] += 1;
+            }
+            counts[color as usize][PieceType::King as usize] = 1;
+        }
+
+        let signature = MaterialSignature { counts };
+        if signature.piece_count() as usize > DTM_MAX_PIECES {
+            return Err(invalid());
+        }
+        Ok(Self::from_signature(&canonical_signature(&signature).0))
+    }
+
+    pub(crate) fn from_signature(signature: &MaterialSignature) -> Self {
+        let mut pieces = vec![(PieceType::King, Color::White), (PieceType::King, Color::Black)];
+        for color in [Color::White, Color::Black] {
+            for piece_type in NON_KING_PIECES {
+                for _ in 0..signature.count(color, piece_type) {
+                    pieces.push((piece_type, color));
+                }
+            }
+        }
+        DtmLayout { signature: *signature, pieces }
+    }
+
+    pub fn name(&self) -> String {
+        self.signature.to_string()
+    }
+
+    pub fn size(&self) -> usize {
+        2 * TRIANGLE_SQUARES.len() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    /// Index of the position with the pieces on `squares` (in layout order), which are transformed in place.
+    pub(crate) fn index(&self, white_to_move: bool, squares: &mut [u8]) -> usize {
+        normalise_squares(squares);
+        let mut index = (!white_to_move as usize) * TRIANGLE_SQUARES.len() + TRIANGLE_INDEX[squares[0] as usize] as usize;
+        for &sq in &squares[1..] {
+            index = index * 64 + sq as usize;
+        }
+        index
+    }
+
+}
+
+/// The material with White as the stronger side, and whether the colours had to be swapped for it.
+pub(crate) fn canonical_signature(signature: &MaterialSignature) -> (MaterialSignature, bool) {
+    let strength = |color: Color| {
+        let c = &signature.counts[color as usize];
+        let value = c[PieceType::Queen as usize] as u32 * 9 + c[PieceType::Rook as usize] as u32 * 5
+            + (c[PieceType::Bishop as usize] + c[PieceType::Knight as usize]) as u32 * 3;
+        (value, c[PieceType::Queen as usize], c[PieceType::Rook as usize], c[PieceType::Bishop as usize])
+    };
+
+    if strength(Color::White) >= strength(Color::Black) {
+        (*signature, false)
+    } else {
+        let [white, black] = signature.counts;
+        (MaterialSignature { counts: [black, white] }, true)
+    }
+}
+
+/// Applies the board symmetry that brings the first square (the white king) into the a1-d1-d4 triangle.
+fn normalise_squares(squares: &mut [u8]) {
+    let transpose = |sq: u8| ((sq & 7) << 3) | (sq >> 3);
+
+    if squares[0] & 7 > 3 {
+        squares.iter_mut().for_each(|sq| *sq ^= 7);
+    }
+    if squares[0] >> 3 > 3 {
+        squares.iter_mut().for_each(|sq| *sq ^= 56);
+    }
+    if squares[0] >> 3 > squares[0] & 7 {
+        squares.iter_mut().for_each(|sq| *sq = transpose(*sq));
+    }
+    if squares[0] >> 3 == squares[0] & 7 {
+        // On the diagonal the mirror image is still in the triangle, the first piece off the diagonal decides
+        if let Some(&sq) = squares[1..].iter().find(|&&sq| sq >> 3 != sq & 7) {
+            if sq >> 3 > sq & 7 {
+                squares.iter_mut().for_each(|sq| *sq = transpose(*sq));
+            }
+        }
+    }
+}
+
+
+pub struct DtmTable {
+    pub layout: DtmLayout,
+    pub(crate) values: Vec<u8>,
+}
+
+impl fmt::Debug for DtmTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DtmTable({}, {} entries)", self.layout.name(), self.values.len())
+    }
+}
+
+/// Outcome of a table position for the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DtmResult {
+    Draw,
+    Win(u8),  // mates in this many plies
+    Loss(u8), // is mated in this many plies
+}
+
+impl DtmResult {
+    pub(crate) fn from_value(value: u8) -> Self {
+        match value {
+            DTM_DRAW => DtmResult::Draw,
+            v if (v - 1) % 2 == 1 => DtmResult::Win(v - 1),
+            v => DtmResult::Loss(v - 1),
+        }
+    }
+}
+
+impl DtmTable {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DtmError> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.take(4)? != DTM_MAGIC {
+            return Err(DtmError::InvalidMagic);
+        }
+        let version = reader.u32()?;
+        if version != DTM_VERSION {
+            return Err(DtmError::UnsupportedVersion(version));
+        }
+        let name_len = reader.u8()? as usize;
+        let name = String::from_utf8_lossy(reader.take(name_len)?).into_owned();
+        let layout = DtmLayout::from_name(&name)?;
+        if layout.name() != name {
+            return Err(DtmError::InvalidMaterial(name));
+        }
+
+        let expected = layout.size();
+        let found = reader.u32()? as usize;
+        if found != expected {
+            return Err(DtmError::SizeMismatch { expected, found });
+        }
+
+        let mut values = Vec::with_capacity(expected);
+        while values.len() < expected {
+            let header = reader.u8()? as usize;
+            if header < 128 {
+                values.extend_from_slice(reader.take(header + 1)?);
+            } else {
+                let value = reader.u8()?;
+                values.extend(std::iter::repeat_n(value, header - 126));
+            }
+        }
+        if values.len() != expected {
+            return Err(DtmError::SizeMismatch { expected, found: values.len() });
+        }
+        if reader.remaining() != 0 {
+            return Err(DtmError::TrailingBytes(reader.remaining()));
+        }
+        Ok(DtmTable { layout, values })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_file(path: &str) -> Result<Self, DtmError> {
+        let bytes = std::fs::read(path).map_err(|e| DtmError::Io(format!("{}: {}", path, e)))?;
+        Self::from_bytes(&bytes)
+    }
+
+}
+
+
+/// Tables by material, with White as the stronger side.
+#[derive(Debug, Default, Clone)]
+pub struct DtmTables {
+    pub(crate) tables: HashMap<MaterialSignature, Arc<DtmTable>>,
+}
+
+impl DtmTables {
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub fn insert(&mut self, table: DtmTable) {
+        self.tables.insert(table.layout.signature, Arc::new(table));
+    }
+
+    /// Raw table value for `board`, positions with only the kings are draws.
+    /// `Err` if the material could be in a table but that table is not loaded, `Ok(None)` if it can't.
+    pub(crate) fn probe_value(&self, board: &Board) -> Result<Option<u8>, DtmError> {
+        let piece_count = board.occupied_bb.popcount() as usize;
+        if piece_count > DTM_MAX_PIECES
+            || board.piece_bbs[PieceType::Pawn as usize].is_not_empty()
+            || board.castling_rights != CastlingRights::NONE
+        {
+            return Ok(None);
+        }
+        if piece_count == 2 {
+            return Ok(Some(DTM_DRAW));
+        }
+
+        let (signature, flip) = canonical_signature(&MaterialSignature::of(board));
+        let table = self.tables.get(&signature).ok_or_else(|| DtmError::MissingSubtable(signature.to_string()))?;
+
+        // Same order as `DtmLayout::pieces`
+        let mut squares = [0u8; DTM_MAX_PIECES];
+        let mut slot = 0;
+        let order = [(PieceType::King, Color::White), (PieceType::King, Color::Black)].into_iter()
+            .chain([Color::White, Color::Black].into_iter().flat_map(|color| NON_KING_PIECES.map(|pt| (pt, color))));
+        for (piece_type, color) in order {
+            let real_color = if flip { color.opponent() } else { color };
+            for sq in (board.piece_bbs[piece_type as usize] & board.color_bbs[real_color as usize]).iter() {
+                squares[slot] = if flip { sq.to_u8() ^ 56 } else { sq.to_u8() };
+                slot += 1;
+            }
+        }
+
+        let white_to_move = (board.turn == Color::White) != flip;
+        let index = table.layout.index(white_to_move, &mut squares[..slot]);
+        Ok(Some(table.values[index]))
+    }
+
+    /// Result for the side to move, if `board` is covered by a loaded table.
+    pub fn probe(&self, board: &Board) -> Option<DtmResult> {
+        self.probe_value(board).ok().flatten().map(DtmResult::from_value)
+    }
+
+}
+
+
+// Shared by every search thread, each search keeps the tables it started with (see `Game::dtm_tables`)
+static DTM_TABLES: Lazy<RwLock<Arc<DtmTables>>> = Lazy::new(|| RwLock::new(Arc::new(DtmTables::default())));
+
+pub fn dtm_tables() -> Arc<DtmTables> {
+    Arc::clone(&DTM_TABLES.read().unwrap())
+}
+
+pub fn set_dtm_tables(tables: DtmTables) {
+    *DTM_TABLES.write().unwrap() = Arc::new(tables);
+}
+
+/// Loads every .cftb file in `dir`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_dtm_directory(dir: &str) -> Result<DtmTables, DtmError> {
+    let mut tables = DtmTables::default();
+    let entries = std::fs::read_dir(dir).map_err(|e| DtmError::Io(format!("{}: {}", dir, e)))?;
+    for entry in entries {
+        let path = entry.map_err(|e| DtmError::Io(e.to_string()))?.path();
+        if path.extension().is_some_and(|ext| ext == "cftb") {
+            tables.insert(DtmTable::from_file(&path.to_string_lossy())?);
+        }
+    }
+    Ok(tables)
+}
+
+
+impl Game {
+    /// Picks up the currently loaded tables (when `use_tablebases` is set), called once at the start of every search.
+    pub fn dtm_begin_search(&mut self) {
+        let tables = dtm_tables();
+        self.dtm_tables = if self.use_tablebases && !tables.is_empty() { Some(tables) } else { None };
+    }
+
+    /// Exact score from White's perspective for positions in the DTM tables, shorter mates score higher.
+    pub fn probe_dtm(&mut self, board: &Board) -> Option<i32> {
+        if board.occupied_bb.popcount() as usize > DTM_MAX_PIECES {
+            return None;
+        }
+        let result = self.dtm_tables.as_ref()?.probe(board)?;
+        self.tb_hits += 1;
+
+        let score = match result {
+            DtmResult::Draw => 0,
+            DtmResult::Win(plies) => TB_WIN_SCORE + DTM_MAX_PLIES as i32 - plies as i32,
+            DtmResult::Loss(plies) => -(TB_WIN_SCORE + DTM_MAX_PLIES as i32 - plies as i32),
+        };
+        Some(if board.turn == Color::White { score } else { -score })
+    }
+}
@@ -0,0 +1,281 @@
+// Specialised evaluation for endgames the generic evaluation gets wrong
+// see: https://www.chessprogramming.org/Endgame
+//      https://www.chessprogramming.org/Material#Imbalance
+
+use crate::board::*;
+use crate::eval_params::*;
+
+use std::fmt;
+
+pub const SCALE_FACTOR_NORMAL: i32 = 64;
+pub const SCALE_FACTOR_DRAW: i32 = 0;
+
+/// Number of pieces of each type per side, indexed `[Color][PieceType]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialSignature {
+    pub counts: [[u8; 6]; 2],
+}
+
+impl MaterialSignature {
+    pub fn of(board: &Board) -> Self {
+        let mut counts = [[0u8; 6]; 2];
+        for color in [Color::White, Color::Black] {
+            for pt in PieceType::ALL {
+                counts[color as usize][pt as usize] = (board.piece_bbs[pt as usize] & board.color_bbs[color as usize]).popcount() as u8;
+            }
+        }
+        MaterialSignature { counts }
+    }
+
+    #[inline]
+    pub fn count(&self, color: Color, piece_type: PieceType) -> u8 {
+        self.counts[color as usize][piece_type as usize]
+    }
+
+    /// Total number of pieces on the board, kings included.
+    pub fn piece_count(&self) -> u32 {
+        self.counts.iter().flatten().map(|&c| c as u32).sum()
+    }
+
+    pub fn is_bare_king(&self, color: Color) -> bool {
+        self.counts[color as usize][..PieceType::King as usize].iter().all(|&c| c == 0)
+    }
+
+    /// True if `color` has exactly the given [queens, rooks, bishops, knights, pawns].
+    pub fn has_exactly(&self, color: Color, pieces: [u8; 5]) -> bool {
+        let c = &self.counts[color as usize];
+        [c[PieceType::Queen as usize], c[PieceType::Rook as usize], c[PieceType::Bishop as usize],
+         c[PieceType::Knight as usize], c[PieceType::Pawn as usize]] == pieces
+    }
+
+    pub fn material(&self, color: Color, params: &EvalParams) -> i32 {
+        self.non_pawn_material(color, params) + self.count(color, PieceType::Pawn) as i32 * params.pawn_value
+    }
+
+    pub fn non_pawn_material(&self, color: Color, params: &EvalParams) -> i32 {
+        self.count(color, PieceType::Knight) as i32 * params.knight_value
+            + self.count(color, PieceType::Bishop) as i32 * params.bishop_value
+            + self.count(color, PieceType::Rook) as i32 * params.rook_value
+            + self.count(color, PieceType::Queen) as i32 * params.queen_value
+    }
+}
+
+/// Material in the usual notation, e.g. "KRPvKR".
+impl fmt::Display for MaterialSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for color in [Color::White, Color::Black] {
+            if color == Color::Black {
+                write!(f, "v")?;
+            }
+            for pt in [PieceType::King, PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight, PieceType::Pawn] {
+                for _ in 0..self.count(color, pt) {
+                    write!(f, "{}", pt.to_char())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+impl Board {
+    /// Score of a specialised endgame evaluator from White's perspective, if one applies.
+    /// All of them need one side to have a bare king.
+    pub fn evaluate_endgame(&self, signature: &MaterialSignature, params: &EvalParams) -> Option<i32> {
+        for strong in [Color::White, Color::Black] {
+            if !signature.is_bare_king(strong.opponent()) {
+                continue;
+            }
+
+            let score = if signature.has_exactly(strong, [0, 0, 0, 0, 1]) {
+                self.evaluate_kpk(strong, params)
+            } else if signature.has_exactly(strong, [0, 0, 1, 1, 0]) {
+                self.evaluate_kbnk(strong, signature, params)
+            } else if self.can_force_mate(strong, signature) {
+                self.evaluate_kxk(strong, signature, params)
+            } else if signature.count(strong, PieceType::Pawn) == 0 {
+                0 // KK, KNK, KNNK, KBK, same coloured bishops
+            } else {
+                return None; // e.g. KBPK, left to the generic evaluation and the scale factors
+            };
+
+            return Some(if strong == Color::White { score } else { -score });
+        }
+        None
+    }
+
+    fn can_force_mate(&self, color: Color, signature: &MaterialSignature) -> bool {
+        let bishops = self.piece_bbs[PieceType::Bishop as usize] & self.color_bbs[color as usize];
+        let has_bishop_pair = bishops.iter().any(|sq| sq.is_light()) && bishops.iter().any(|sq| !sq.is_light());
+
+        signature.count(color, PieceType::Queen) > 0
+            || signature.count(color, PieceType::Rook) > 0
+            || has_bishop_pair
+            || (signature.count(color, PieceType::Bishop) > 0 && signature.count(color, PieceType::Knight) > 0)
+    }
+
+    /// Mating material against a bare king: drive the king to the edge and bring our own king closer.
+    fn evaluate_kxk(&self, strong: Color, signature: &MaterialSignature, params: &EvalParams) -> i32 {
+        let strong_king = self.find_king_square(strong).unwrap();
+        let weak_king = self.find_king_square(strong.opponent()).unwrap();
+
+        params.known_win_bonus
+            + signature.material(strong, params)
+            + (3 - weak_king.edge_distance() as i32) * params.push_to_edge_weight
+            + (7 - strong_king.distance(weak_king) as i32) * params.push_close_weight
+    }
+
+    /// Bishop and knight mate: only the corners of the bishop's colour can be mated in.
+    fn evaluate_kbnk(&self, strong: Color, signature: &MaterialSignature, params: &EvalParams) -> i32 {
+        let strong_king = self.find_king_square(strong).unwrap();
+        let weak_king = self.find_king_square(strong.opponent()).unwrap();
+        let bishop = (self.piece_bbs[PieceType::Bishop as usize] & self.color_bbs[strong as usize]).lsb().unwrap();
+
+        let (corner_a, corner_b) = if bishop.is_light() { (Square::A8, Square::H1) } else { (Square::A1, Square::H8) };
+        let corner_distance = weak_king.distance(corner_a).min(weak_king.distance(corner_b)) as i32;
+
+        params.known_win_bonus
+            + signature.material(strong, params)
+            + (7 - corner_distance) * params.push_to_corner_weight
+            + (7 - strong_king.distance(weak_king) as i32) * params.push_close_weight
+    }
+
+    /// King and pawn against king, exact result from the bitbase (see kpk.rs).
+    /// Won positions get a bonus for advancing the pawn so the search makes progress.
+    fn evaluate_kpk(&self, strong: Color, params: &EvalParams) -> i32 {
+        if self.probe_kpk() != Some(true) {
+            return 0;
+        }
+
+        let pawn = (self.piece_bbs[PieceType::Pawn as usize] & self.color_bbs[strong as usize]).lsb().unwrap();
+        let relative_rank = match strong {
+            Color::White => pawn.rank() as i32,
+            Color::Black => 7 - pawn.rank() as i32,
+        };
+        params.known_win_bonus + params.pawn_value + relative_rank * 10
+    }
+
+    /// Scale factor (out of `SCALE_FACTOR_NORMAL`) for the generic evaluation when `strong` is ahead.
+    pub fn endgame_scale_factor(&self, signature: &MaterialSignature, strong: Color, params: &EvalParams) -> i32 {
+        let weak = strong.opponent();
+        let strong_pawns = self.piece_bbs[PieceType::Pawn as usize] & self.color_bbs[strong as usize];
+
+        // Rook pawns and a bishop that does not control the promotion square, with the defending king in the corner
+        // see: https://www.chessprogramming.org/Wrong_Color_Bishop_and_Rook_Pawn
+        if signature.count(strong, PieceType::Queen) == 0 && signature.count(strong, PieceType::Rook) == 0
+            && signature.count(strong, PieceType::Knight) == 0 && signature.count(strong, PieceType::Bishop) > 0
+            && strong_pawns.is_not_empty()
+        {
+            for file in [0, 7] {
+                if (strong_pawns & PRECOMPUTED.file_masks[file as usize]) != strong_pawns {
+                    continue;
+                }
+                let promotion_sq = Square::from_file_rank(file, if strong == Color::White { 7 } else { 0 });
+                let bishops = self.piece_bbs[PieceType::Bishop as usize] & self.color_bbs[strong as usize];
+                let wrong_bishop = bishops.iter().all(|sq| sq.is_light() != promotion_sq.is_light());
+                let weak_king = self.find_king_square(weak).unwrap();
+                if wrong_bishop && weak_king.distance(promotion_sq) <= 1 {
+                    return SCALE_FACTOR_DRAW;
+                }
+            }
+        }
+
+        // Opposite coloured bishops with nothing else but pawns
+        if signature.has_exactly(strong, [0, 0, 1, 0, signature.count(strong, PieceType::Pawn)])
+            && signature.has_exactly(weak, [0, 0, 1, 0, signature.count(weak, PieceType::Pawn)])
+        {
+            let strong_bishop = (self.piece_bbs[PieceType::Bishop as usize] & self.color_bbs[strong as usize]).lsb().unwrap();
+            let weak_bishop = (self.piece_bbs[PieceType::Bishop as usize] & self.color_bbs[weak as usize]).lsb().unwrap();
+            if strong_bishop.is_light() != weak_bishop.is_light() {
+                return params.opposite_bishops_scale;
+            }
+        }
+
+        // Without pawns, being up less than a minor piece is rarely enough to win (KRKB, KRKN, KBKN, ...)
+        if strong_pawns.is_empty()
+            && signature.non_pawn_material(strong, params) - signature.non_pawn_material(weak, params) <= params.bishop_value
+        {
+            return params.pawnless_scale;
+        }
+
+        SCALE_FACTOR_NORMAL
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal_states::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(&fen.parse::<Fen>().unwrap()).unwrap()
+    }
+
+    fn endgame_score(fen: &str) -> Option<i32> {
+        let board = board(fen);
+        board.evaluate_endgame(&MaterialSignature::of(&board), &EvalParams::default())
+    }
+
+    fn scale_factor(fen: &str, strong: Color) -> i32 {
+        let board = board(fen);
+        board.endgame_scale_factor(&MaterialSignature::of(&board), strong, &EvalParams::default())
+    }
+
+    #[test]
+    fn drives_the_king_toward_the_corner_of_the_bishop_colour() {
+        // Light-squared bishop on h7: a8 and h1 are the mating corners
+        let near_h1 = endgame_score("8/7B/8/4N3/8/4K3/6k1/8 w - - 0 1").unwrap();
+        let near_a1 = endgame_score("8/7B/8/4N3/8/3K4/1k6/8 w - - 0 1").unwrap();
+        assert!(near_h1 > near_a1);
+        assert!(near_a1 > EvalParams::default().known_win_bonus);
+
+        // Same for Black with a dark-squared bishop: a1 and h8 are the mating corners
+        let near_a1 = endgame_score("1b1n4/8/8/8/8/3k4/1K6/8 b - - 0 1").unwrap();
+        let near_h1 = endgame_score("1b1n4/8/8/8/8/4k3/6K1/8 b - - 0 1").unwrap();
+        assert!(near_a1 < near_h1);
+        assert!(near_h1 < -EvalParams::default().known_win_bonus);
+    }
+
+    #[test]
+    fn scores_insufficient_material_as_a_draw() {
+        assert_eq!(endgame_score("8/8/8/4k3/8/8/2N5/4K3 w - - 0 1"), Some(0));
+        assert_eq!(endgame_score("8/8/8/4k3/8/8/2B1B3/4K3 w - - 0 1"), Some(0)); // both bishops on light squares
+        assert_eq!(endgame_score("8/8/8/4k3/8/3P4/2B5/4K3 w - - 0 1"), None); // KBPK is left to the generic evaluation
+    }
+
+    #[test]
+    fn wrong_bishop_and_rook_pawn_is_a_draw() {
+        // Dark-squared bishop, a8 is light
+        assert_eq!(scale_factor("k7/8/P7/8/8/8/8/2B1K3 w - - 0 1", Color::White), SCALE_FACTOR_DRAW);
+        assert_eq!(scale_factor("1k6/8/P7/8/8/8/8/2B1K3 w - - 0 1", Color::White), SCALE_FACTOR_DRAW);
+        let drawn = board("k7/8/P7/8/8/8/8/2B1K3 w - - 0 1");
+        assert_eq!(drawn.evaluate(0, &mut Vec::new(), &mut Vec::new(), GameState::Ongoing, &EvalParams::default()), 0);
+
+        // The defending king too far away, the right bishop, or a pawn on another file
+        assert_eq!(scale_factor("8/8/P2k4/8/8/8/8/2B1K3 w - - 0 1", Color::White), SCALE_FACTOR_NORMAL);
+        assert_eq!(scale_factor("k7/8/P7/8/8/8/8/3BK3 w - - 0 1", Color::White), SCALE_FACTOR_NORMAL);
+        assert_eq!(scale_factor("k7/8/PP6/8/8/8/8/2B1K3 w - - 0 1", Color::White), SCALE_FACTOR_NORMAL);
+
+        // Black pawn on the h-file promotes on h1 (light), a dark-squared bishop cannot help
+        assert_eq!(scale_factor("4k3/8/8/8/8/7p/8/2b4K b - - 0 1", Color::Black), SCALE_FACTOR_DRAW);
+    }
+
+    #[test]
+    fn scales_down_opposite_coloured_bishops() {
+        let params = EvalParams::default();
+        // White bishop on d3 (light), black bishop on e7 (dark)
+        assert_eq!(scale_factor("4k3/p3bppp/8/8/8/3B4/PP3PPP/4K3 w - - 0 1", Color::White), params.opposite_bishops_scale);
+        // Same coloured bishops (d3 and e6 are both light)
+        assert_eq!(scale_factor("4k3/p4ppp/4b3/8/8/3B4/PP3PPP/4K3 w - - 0 1", Color::White), SCALE_FACTOR_NORMAL);
+        // Another piece on the board
+        assert_eq!(scale_factor("4k3/p3bppp/8/8/8/3B4/PP3PPP/R3K3 w - - 0 1", Color::White), SCALE_FACTOR_NORMAL);
+    }
+
+    #[test]
+    fn scales_down_pawnless_endings_without_a_clear_material_edge() {
+        let params = EvalParams::default();
+        assert_eq!(scale_factor("4k3/8/8/8/3b4/8/8/R3K3 w - - 0 1", Color::White), params.pawnless_scale);
+        assert_eq!(scale_factor("4k3/8/8/8/3b4/8/8/R2QK3 w - - 0 1", Color::White), SCALE_FACTOR_NORMAL);
+    }
+}
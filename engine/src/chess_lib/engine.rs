@@ -1,5 +1,18 @@
 use crate::game::*;
 use crate::board::*;
+use crate::eval_params::*;
+use crate::nnue::*;
+use crate::kpk::*;
+use crate::syzygy::*;
+use crate::dtm::*;
+use crate::polyglot::*;
+use crate::search::*;
+use crate::evaluate::*;
+use crate::uci_options::*;
+use crate::xboard_parser::*;
+use crate::time_manager::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::bench::*;
 
 use futures::channel::mpsc as futures_mpsc;
 use futures::SinkExt;
@@ -8,8 +21,8 @@ use futures::StreamExt;
 
 use std::{
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
     },
     thread,
 };
@@ -69,48 +82,208 @@ macro_rules! send_response {
 }
 
 enum SearchTask {
-    Go { game: Game, depth: u8, is_eval: bool },
+    Go { game: Game, generation: u64, depth: u8, is_eval: bool, multi_pv: u8, mate: Option<u8>, pondering: Arc<AtomicBool>, hold: Arc<HoldSignal>, infinite: bool, show_ponder: bool, debug: bool, output: SearchOutput },
     Quit,
 }
 
+/// Wakes the search thread while it holds back the answer of a ponder or infinite search,
+/// notified whenever `pondering` or the stop signal change.
+#[derive(Default)]
+struct HoldSignal {
+    lock: Mutex<()>,
+    changed: Condvar,
+}
+
+impl HoldSignal {
+    /// Blocks while `hold` returns true, it is checked again on every `notify`.
+    fn wait_while(&self, mut hold: impl FnMut() -> bool) {
+        let guard = self.lock.lock().unwrap();
+        let _guard = self.changed.wait_while(guard, |_| hold()).unwrap();
+    }
+
+    /// Call after changing what the waiting thread checks.
+    fn notify(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.changed.notify_all();
+    }
+}
+
+/// Protocol the search thread reports in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SearchOutput {
+    Uci,
+    Xboard { post: bool }, // `post`: send thinking output
+}
+
+/// What a finished search hands back to the engine (see `Engine::reclaim_search_tables`).
+struct FinishedSearch {
+    generation: u64,
+    tables: SearchTables,
+    announced_move: Option<ChessMove>, // sent with xboard's `move`, the engine's board still has to play it
+}
+
+/// Settings of the xboard front-end, kept between commands.
+#[derive(Debug, Clone, Default)]
+struct XboardState {
+    force: bool, // only record the moves, do not think
+    post: bool,
+    moves_per_session: u32, // `level`, 0 for the whole game
+    base_ms: Option<u64>,
+    increment_ms: u64,
+    move_time_ms: Option<u64>, // `st`
+    depth: Option<u8>, // `sd`
+    engine_ms: Option<u64>, // `time`
+    opponent_ms: Option<u64>, // `otim`
+}
+
+/// Depth limit of timed and infinite searches without `depth`, the maximum of the max_depth option.
+const MAX_TIMED_DEPTH: u8 = 20;
+
+/// Centipawns from the side to move's point of view, as UCI expects, for a score from White's perspective.
+pub fn uci_score_cp(board: &Board, score: i32) -> i32 {
+    if board.turn == Color::White { score } else { -score }
+}
+
+/// Moves until mate from the side to move's point of view (negative if it gets mated), see `mate_distance`.
+pub fn uci_mate_distance(board: &Board, score: i32, depth: u8) -> Option<i32> {
+    let moves = mate_distance(score, depth)?;
+    Some(if board.turn == Color::White { moves } else { -moves })
+}
+
+/// `cp <centipawns>` or `mate <moves>` for the `score` of an `info` line.
+pub fn uci_score(board: &Board, score: i32, depth: u8) -> String {
+    match uci_mate_distance(board, score, depth) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", uci_score_cp(board, score)),
+    }
+}
+
+/// Score of xboard thinking output: centipawns for the side to move, mates as 100000 + moves (negative if mated).
+pub fn xboard_score(board: &Board, score: i32, depth: u8) -> i32 {
+    match uci_mate_distance(board, score, depth) {
+        Some(moves) if moves > 0 => 100_000 + moves,
+        Some(moves) => -100_000 + moves,
+        None => uci_score_cp(board, score),
+    }
+}
+
 pub struct Engine {
     game: Game,
     stop_signal: Arc<AtomicBool>,
     max_depth: u8,
     max_q_depth: u8,
+    hash_mb: usize, // `Hash`, size limit of the transposition table
     search_sender: CrossbeamSender<SearchTask>,
+    finished_receiver: CrossbeamReceiver<FinishedSearch>,
+    tables_generation: Arc<AtomicU64>, // changes whenever the search tables stop belonging to the current game
+    announce_lock: Arc<Mutex<()>>, // held while the generation changes and while a search announces its xboard move
+    xboard: Option<XboardState>, // speaking xboard instead of UCI
+    debug: bool, // `debug on`, explains what the engine does in `info string` lines
     is_evaluation_mode: bool,
+    use_nnue: bool,
+    own_book: bool,
+    best_book_move: bool, // `BookMoves` is "Best" rather than "Weighted"
+    multi_pv: u8,
+    ponder: bool, // `Ponder` option, adds the expected reply to `bestmove`
+    pondering: Arc<AtomicBool>, // a `go ponder` search is waiting for `ponderhit`
+    hold: Arc<HoldSignal>, // wakes a search waiting for `ponderhit` or `stop`
+    ponder_budget_ms: Option<u64>, // thinking time of the ponder search once `ponderhit` arrives
+    deadline_ms: Arc<AtomicU64>, // shared with the games of the searches
 }
 
 
 impl Engine {
     pub fn new() -> Self {
         let (search_sender, search_receiver) = unbounded::<SearchTask>();
+        let (finished_sender, finished_receiver) = unbounded::<FinishedSearch>();
+        let tables_generation = Arc::new(AtomicU64::new(0));
+        let current_generation = Arc::clone(&tables_generation);
+        let announce_lock = Arc::new(Mutex::new(()));
+        let search_announce_lock = Arc::clone(&announce_lock);
         
         let search_thread_loop = move || {
             log!("Spawned search thread: '{}' started. Waiting for tasks.", thread::current().name().unwrap_or("unnamed"));
+            init_kpk_bitbase();
             loop {
                 match search_receiver.recv() {
-                    Ok(SearchTask::Go { mut game, depth, is_eval }) => {
-                        if is_eval {
+                    Ok(SearchTask::Go { mut game, generation, depth, is_eval, multi_pv, mate, pondering, hold, infinite, show_ponder, debug, output }) => {
+                        if is_eval && multi_pv == 1 {
                             let pseudo_legal_moves : &mut Vec<ChessMove> = &mut Vec::with_capacity(200);
                             let legal_moves : &mut Vec<ChessMove> = &mut Vec::with_capacity(100);
                             for i in 1..=depth {
                                 let best_move = game.find_best_move(i);
                                 let gamestate = game.get_game_state();
-                                let eval = game.board.evaluate(0, pseudo_legal_moves, legal_moves, gamestate);
+                                game.board.generate_legal_moves(pseudo_legal_moves, legal_moves); // for the mobility term
+                                let eval = game.board.evaluate(0, pseudo_legal_moves, legal_moves, gamestate, &game.eval_params);
                                 if let Some(mv) = best_move {
-                                    send_response!("info depth {} score cp {} pv {}", i + game.q_search_max_ply, eval as f32 / 10.0, mv.to_uci());
+                                    send_response!("info depth {} score cp {} tbhits {} pv {}", i + game.q_search_max_ply, uci_score_cp(&game.board, eval), game.tb_hits, mv.to_uci());
                                 } else {
-                                    send_response!("info depth {} score cp {} pv", i + game.q_search_max_ply, eval as f32 / 10.0);
+                                    send_response!("info depth {} score cp {} tbhits {} pv", i + game.q_search_max_ply, uci_score_cp(&game.board, eval), game.tb_hits);
                                 }
                             }
+                            let _ = finished_sender.send(FinishedSearch { generation, tables: game.take_search_tables(), announced_move: None });
                         } else {
-                            let best_move = game.find_best_move(depth);
-                            if let Some(mv) = best_move {
-                                send_response!("bestmove {}", mv.to_uci());
-                            } else {
-                                send_response!("bestmove (none)");
+                            // Iterative deepening, reporting every line of each finished iteration
+                            let mut best_line: Option<PvLine> = None;
+                            for i in 1..=depth {
+                                let lines = game.find_best_lines(i, multi_pv as usize);
+                                if game.should_stop() {
+                                    if debug && best_line.is_some() && output == SearchOutput::Uci {
+                                        send_response!("info string depth {} was not finished, using the result of depth {}", i, i - 1);
+                                    }
+                                    best_line = best_line.or(lines.into_iter().next());
+                                    break;
+                                }
+                                let time = now_ms().saturating_sub(game.search_start_ms);
+                                for (k, line) in lines.iter().enumerate() {
+                                    let pv: Vec<String> = line.moves.iter().map(|mv| mv.to_uci()).collect();
+                                    match output {
+                                        SearchOutput::Uci => send_response!("info depth {} multipv {} score {} nodes {} nps {} time {} tbhits {} pv {}",
+                                                                            i, k + 1, uci_score(&game.board, line.score, i), game.nodes, game.nodes * 1000 / time.max(1),
+                                                                            time, game.tb_hits, pv.join(" ")),
+                                        // ply score time(centiseconds) nodes pv
+                                        SearchOutput::Xboard { post: true } if k == 0 => send_response!("{} {} {} {} {}",
+                                                                            i, xboard_score(&game.board, line.score, i), time / 10, game.nodes, pv.join(" ")),
+                                        SearchOutput::Xboard { .. } => {}
+                                    }
+                                }
+                                best_line = lines.into_iter().next();
+
+                                // `go mate`: done once the side to move has a short enough mate
+                                if let (Some(mate), Some(line)) = (mate, &best_line) {
+                                    if uci_mate_distance(&game.board, line.score, i).is_some_and(|moves| moves > 0 && moves <= mate as i32) {
+                                        break;
+                                    }
+                                }
+                            }
+
+                            if !is_eval {
+                                // A ponder search must not answer before `ponderhit` or `stop`, an infinite one before `stop`,
+                                // even if it is done
+                                hold.wait_while(|| (infinite || pondering.load(Ordering::Relaxed)) && !game.stop_signal.load(Ordering::Relaxed));
+                            }
+                            // Handed back before `bestmove`, so the tables are there when the GUI sends the next position.
+                            // The xboard move is only sent for the current game, not after `force`, `new` or `result`.
+                            // The generation cannot change while the lock is held, and the engine takes back the
+                            // result before changing it, so an announced move is always played on its game.
+                            let best_move = best_line.as_ref().map(|line| line.moves[0]);
+                            let _announcing = search_announce_lock.lock().unwrap();
+                            let announced_move = best_move.filter(|_| {
+                                !is_eval && output != SearchOutput::Uci && current_generation.load(Ordering::Relaxed) == generation
+                            });
+                            let _ = finished_sender.send(FinishedSearch { generation, tables: game.take_search_tables(), announced_move });
+                            match output {
+                                _ if is_eval => {}
+                                SearchOutput::Uci => match best_line {
+                                    Some(line) if show_ponder && line.moves.len() > 1 => send_response!("bestmove {} ponder {}", line.moves[0].to_uci(), line.moves[1].to_uci()),
+                                    Some(line) => send_response!("bestmove {}", line.moves[0].to_uci()),
+                                    None => send_response!("bestmove (none)"),
+                                },
+                                SearchOutput::Xboard { .. } => {
+                                    if let Some(mv) = announced_move {
+                                        send_response!("move {}", mv.to_uci());
+                                    }
+                                }
                             }
                         }
                     },
@@ -129,86 +302,412 @@ impl Engine {
         #[cfg(not(target_arch = "wasm32"))]
         thread::spawn(search_thread_loop);
 
-        let max_depth = 5; // default search depth
-        let max_q_depth = 3; // default quiescence search depth
+        let max_depth = DEFAULT_MAX_DEPTH;
+        let max_q_depth = DEFAULT_MAX_Q_DEPTH;
         let stop_signal = Arc::new(AtomicBool::new(false));
+        let deadline_ms = Arc::new(AtomicU64::new(NO_DEADLINE));
+        let mut game = Game::new(Board::new_start_pos(), max_depth, max_q_depth, Arc::clone(&stop_signal));
+        game.deadline_ms = Arc::clone(&deadline_ms);
 
         Engine {
-            game: Game::new(Board::new_start_pos(), max_depth, max_q_depth, Arc::clone(&stop_signal)),
+            game,
             stop_signal,
             max_depth,
             max_q_depth,
+            hash_mb: DEFAULT_HASH_MB,
             search_sender,
+            finished_receiver,
+            tables_generation,
+            announce_lock,
+            xboard: None,
+            debug: false,
             is_evaluation_mode: false,
+            use_nnue: false,
+            own_book: false,
+            best_book_move: false,
+            multi_pv: 1,
+            ponder: false,
+            pondering: Arc::new(AtomicBool::new(false)),
+            hold: Arc::new(HoldSignal::default()),
+            ponder_budget_ms: None,
+            deadline_ms,
+        }
+    }
+
+    /// Creates a game for `board` with the engine's current settings.
+    fn new_game(&self, board: Board) -> Game {
+        let mut game = Game::new(board, self.max_depth, self.max_q_depth, Arc::clone(&self.stop_signal));
+        game.use_nnue = self.use_nnue;
+        game.set_hash_size(self.hash_mb);
+        game.deadline_ms = Arc::clone(&self.deadline_ms);
+        game
+    }
+
+    /// Takes back the search tables of finished searches, unless they belong to an earlier game.
+    /// Tables of a search that is still running are lost, the next search then starts with empty ones.
+    /// With xboard the move the search announced is played here too, the GUI does not send it back.
+    fn reclaim_search_tables(&mut self) {
+        while let Ok(finished) = self.finished_receiver.try_recv() {
+            if finished.generation == self.tables_generation.load(Ordering::Relaxed) {
+                self.game.restore_search_tables(finished.tables);
+                if let Some(mv) = finished.announced_move {
+                    self.game.make_move(&mv);
+                }
+            }
+        }
+    }
+
+    /// Makes the search tables of running and finished searches stale. A move announced before that is played
+    /// first, so the engine's board matches what the GUI was told.
+    fn new_tables_generation(&mut self) {
+        let announce_lock = Arc::clone(&self.announce_lock);
+        let _generation_change = announce_lock.lock().unwrap();
+        self.reclaim_search_tables();
+        self.tables_generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `info string` for UCI, a comment line for xboard.
+    fn send_info(&self, message: &str) {
+        match self.xboard {
+            None => send_response!("info string {}", message),
+            Some(_) => send_response!("# {}", message),
+        }
+    }
+
+    /// `send_info`, only after `debug on`.
+    fn send_debug(&self, message: &str) {
+        if self.debug {
+            self.send_info(message);
+        }
+    }
+
+    /// Applies a checked `setoption` value. The current game is kept, settings it carries are updated in place.
+    fn apply_option(&mut self, name: &'static str, value: UciOptionValue) {
+        match (name, value) {
+            ("max_depth", UciOptionValue::Spin(depth)) => {
+                self.max_depth = depth as u8;
+            }
+            ("max_q_depth", UciOptionValue::Spin(depth)) => {
+                self.max_q_depth = depth as u8;
+                self.game.q_search_max_ply = self.max_q_depth;
+            }
+            ("is_evaluation_mode", UciOptionValue::Check(enabled)) => {
+                self.is_evaluation_mode = enabled;
+            }
+            ("MultiPV", UciOptionValue::Spin(lines)) => {
+                self.multi_pv = lines as u8;
+            }
+            ("Ponder", UciOptionValue::Check(enabled)) => {
+                self.ponder = enabled;
+            }
+            ("Hash", UciOptionValue::Spin(mb)) => {
+                self.hash_mb = mb as usize;
+                self.game.set_hash_size(self.hash_mb);
+            }
+            ("Clear Hash", UciOptionValue::Button) => {
+                self.new_tables_generation();
+                self.game.clear_search_tables();
+            }
+            ("EvalFile", UciOptionValue::String(value)) => {
+                if value.is_empty() {
+                    set_eval_params(EvalParams::default());
+                } else {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    match EvalParams::from_file(&value) {
+                        Ok(params) => {
+                            set_eval_params(params);
+                            send_response!("info string loaded evaluation parameters from {}", value);
+                        }
+                        Err(e) => send_response!("info string {}", e),
+                    }
+                    #[cfg(target_arch = "wasm32")]
+                    send_response!("info string EvalFile is not supported in the browser, use load_eval_params instead");
+                }
+            }
+            ("UseNNUE", UciOptionValue::Check(enabled)) => {
+                self.use_nnue = enabled;
+                self.game.use_nnue = enabled;
+                if self.use_nnue && nnue_network().is_none() {
+                    send_response!("info string UseNNUE is set but no network is loaded, using the handcrafted evaluation");
+                }
+            }
+            ("NNUEFile", UciOptionValue::String(value)) => {
+                if value.is_empty() {
+                    set_nnue_network(None);
+                } else {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    match Network::from_file(&value) {
+                        Ok(network) => {
+                            send_response!("info string loaded NNUE network {:?} from {}", network, value);
+                            set_nnue_network(Some(network));
+                        }
+                        Err(e) => send_response!("info string {}", e),
+                    }
+                    #[cfg(target_arch = "wasm32")]
+                    send_response!("info string NNUEFile is not supported in the browser, use load_nnue_network instead");
+                }
+            }
+            ("SyzygyPath", UciOptionValue::String(value)) => {
+                if value.is_empty() {
+                    set_syzygy_tablebases(None);
+                } else {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    match Tablebases::open(&value) {
+                        Ok(tablebases) => {
+                            send_response!("info string found {} Syzygy tables with up to {} pieces", tablebases.file_count, tablebases.max_pieces);
+                            set_syzygy_tablebases(Some(tablebases));
+                        }
+                        Err(e) => send_response!("info string {}", e),
+                    }
+                    #[cfg(target_arch = "wasm32")]
+                    send_response!("info string SyzygyPath is not supported in the browser");
+                }
+            }
+            ("DTMPath", UciOptionValue::String(value)) => {
+                if value.is_empty() {
+                    set_dtm_tables(DtmTables::default());
+                } else {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    match load_dtm_directory(&value) {
+                        Ok(tables) => {
+                            send_response!("info string loaded {} DTM tables from {}", tables.len(), value);
+                            set_dtm_tables(tables);
+                        }
+                        Err(e) => send_response!("info string {}", e),
+                    }
+                    #[cfg(target_arch = "wasm32")]
+                    send_response!("info string DTMPath is not supported in the browser, use load_dtm_table instead");
+                }
+            }
+            ("OwnBook", UciOptionValue::Check(enabled)) => {
+                self.own_book = enabled;
+                if self.own_book && opening_book().is_none() {
+                    send_response!("info string OwnBook is set but no opening book is loaded");
+                }
+            }
+            ("BookFile", UciOptionValue::String(value)) => {
+                if value.is_empty() {
+                    set_opening_book(None);
+                } else {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    match PolyglotBook::from_file(&value) {
+                        Ok(book) => {
+                            send_response!("info string loaded {} book entries from {}", book.len(), value);
+                            set_opening_book(Some(book));
+                        }
+                        Err(e) => send_response!("info string {}", e),
+                    }
+                    #[cfg(target_arch = "wasm32")]
+                    send_response!("info string BookFile is not supported in the browser, use load_opening_book instead");
+                }
+            }
+            ("BookMoves", UciOptionValue::Combo(selection)) => {
+                self.best_book_move = selection == "Best";
+            }
+            (name, value) => unreachable!("option {} does not take {:?}", name, value),
+        }
+    }
+
+    /// Starts a search of the current game on the search thread, or answers with a book move.
+    fn start_search(&mut self, params: GoParams) {
+        // Book moves are answered right away, without waking the search thread
+        if self.own_book && !self.is_evaluation_mode && !params.ponder {
+            let book_move = opening_book().and_then(|book| {
+                if self.best_book_move { book.best_move(&self.game.board) } else { book.pick_move(&self.game.board, &mut rand::rng()) }
+            });
+            if let Some(mv) = book_move {
+                self.send_info(&format!("book move {}", mv.to_uci()));
+                if self.xboard.is_some() {
+                    self.game.make_move(&mv);
+                    send_response!("move {}", mv.to_uci());
+                } else {
+                    send_response!("bestmove {}", mv.to_uci());
+                }
+                return;
+            }
+        }
+
+        self.stop_signal.store(false, Ordering::Relaxed);
+        // The search thread gets the tables and hands them back when it is done (see `reclaim_search_tables`)
+        self.reclaim_search_tables();
+        let tables = self.game.take_search_tables();
+        let mut game_clone = self.game.clone();
+        game_clone.restore_search_tables(tables);
+        let depth = match (params.depth, params.mate) {
+            (Some(depth), _) => depth,
+            (None, Some(mate)) => mate.saturating_mul(2).saturating_sub(1).min(MAX_TIMED_DEPTH), // the mating side moves last
+            (None, None) if params.is_timed() || params.nodes.is_some() => MAX_TIMED_DEPTH,
+            (None, None) => self.max_depth,
+        };
+        if depth > game_clone.max_search_depth {
+            game_clone.set_max_search_depth(depth);
+        }
+        game_clone.node_limit = params.nodes;
+        for mv in &params.searchmoves {
+            match game_clone.board.parse_uci_move(mv) {
+                Ok(parsed) => game_clone.search_moves.push(parsed),
+                Err(e) => self.send_info(&format!("ignoring searchmoves entry {}: {}", mv, e)),
+            }
+        }
+        game_clone.report_progress = self.xboard.is_none(); // xboard has no such output
+        game_clone.search_start_ms = now_ms();
+        game_clone.last_progress_ms = game_clone.search_start_ms;
+
+        // A ponder search runs until `ponderhit` starts the clock, or `stop`
+        let budget = params.time_budget_ms(game_clone.board.turn);
+        self.ponder_budget_ms = budget;
+        self.pondering.store(params.ponder, Ordering::Relaxed);
+        let deadline = match budget {
+            Some(ms) if !params.ponder => {
+                self.send_debug(&format!("thinking for up to {} ms", ms));
+                now_ms() + ms
+            }
+            _ => NO_DEADLINE,
+        };
+        self.deadline_ms.store(deadline, Ordering::Relaxed);
+        self.send_debug(&format!("searching to depth {}, node limit {:?}, {} hash entries", depth,
+                                 game_clone.node_limit, game_clone.transposition_table.len()));
+
+        self.search_sender.send(SearchTask::Go {
+            game: game_clone,
+            generation: self.tables_generation.load(Ordering::Relaxed),
+            depth,
+            is_eval: self.is_evaluation_mode && self.xboard.is_none(),
+            multi_pv: self.multi_pv,
+            mate: params.mate,
+            pondering: Arc::clone(&self.pondering),
+            hold: Arc::clone(&self.hold),
+            infinite: params.infinite,
+            show_ponder: self.ponder,
+            debug: self.debug,
+            output: self.xboard.as_ref().map_or(SearchOutput::Uci, |xboard| SearchOutput::Xboard { post: xboard.post }),
+        }).expect("Failed to send search task");
+    }
+
+    /// Parses and runs one line of input, errors are reported with `info string`.
+    pub fn process_line(&mut self, line: &str) {
+        match parse_command(line) {
+            Ok(command) => self.process_command(command),
+            Err(UciParseError::MissingCommand) => {} // empty line
+            Err(e) => send_response!("info string {}", e),
         }
     }
 
     pub fn process_command(&mut self, command: UciCommand) {
         match command {
             UciCommand::Uci => {
-                send_response!(
-                    "id name ChessFlow\n\
-                    id author ChessFlow\n\
-                    option name max_depth type spin default 7 min 1 max 20\n\
-                    option name max_q_depth type spin default 3 min 1 max 10\n\
-                    option name is_evaluation_mode type check default false\n\
-                    uciok");
+                let mut reply = String::from("id name ChessFlow\nid author ChessFlow\n");
+                for option in UCI_OPTIONS {
+                    reply.push_str(&option.uci_line());
+                    reply.push('\n');
+                }
+                reply.push_str("uciok");
+                send_response!("{}", reply);
             }
             UciCommand::SetOption { name, value } => {
                 log!("SetOption: {} = {}", name, value);
-                match name.to_ascii_lowercase().as_str() {
-                    "max_depth" => {
-                        let val = value.parse().unwrap_or(7);
-                        if val >= 1 && val <= 20 {
-                            self.max_depth = val;
-                        }
-                    },
-                    "max_q_depth" => {
-                        let val = value.parse().unwrap_or(3);
-                        if val >= 1 && val <= 10 {
-                            self.max_q_depth = val;
-                        }
-                    },
-                    "is_evaluation_mode" => {
-                        let val = value.parse().unwrap_or(false);
-                        self.is_evaluation_mode = val;
+                let Some(option) = find_uci_option(&name) else {
+                    send_response!("info string {}", UciOptionError::UnknownOption(name));
+                    return;
+                };
+                match option.parse_value(&value) {
+                    Ok(parsed) => {
+                        self.send_debug(&format!("setting {} to '{}'", option.name, value));
+                        self.apply_option(option.name, parsed);
                     }
-                    _ => {}
+                    Err(e) => send_response!("info string {}", e),
                 }
-                self.game = Game::new(Board::new_start_pos(), self.max_depth, self.max_q_depth, Arc::clone(&self.stop_signal));
             }
             UciCommand::IsReady => {
                 send_response!("readyok");
             }
             UciCommand::NewGame => {
-                self.game = Game::new(Board::new_start_pos(), self.max_depth, self.max_q_depth, Arc::clone(&self.stop_signal));
+                self.new_tables_generation();
+                self.game = self.new_game(Board::new_start_pos());
             }
             UciCommand::Position { fen, moves } => {
-                let board = if let Some(fen) = fen {
-                    Board::from_fen(&fen).expect("Invalid FEN")
+                // Nothing changes unless the whole command is valid
+                let board = match fen.map(|fen| Board::from_fen(&fen)) {
+                    Some(Ok(board)) => board,
+                    Some(Err(e)) => {
+                        send_response!("info string position ignored: {}", e);
+                        return;
+                    }
+                    None => Board::new_start_pos(),
+                };
+
+                // GUIs resend the whole game with one more move each turn: only the new moves are played and the
+                // search tables are kept. Any other position starts over with empty tables.
+                self.reclaim_search_tables();
+                let played: Vec<String> = self.game.move_history.iter().map(|mv| mv.to_uci()).collect();
+                let continues = board == self.game.start_board && moves.starts_with(&played);
+                let tables = self.game.take_search_tables();
+                let (mut game, new_moves) = if continues {
+                    (self.game.clone(), &moves[played.len()..])
                 } else {
-                    Board::new_start_pos()
+                    (self.new_game(board), &moves[..])
                 };
-                self.game = Game::new(board, self.max_depth, self.max_q_depth, Arc::clone(&self.stop_signal));
-                for mv_str in moves {
-                    if let Ok(mv) = parse_move_string(&mv_str) {
-                        self.game.make_move(&mv);
+                for mv_str in new_moves {
+                    match game.board.parse_uci_move(mv_str) {
+                        Ok(mv) => game.make_move(&mv),
+                        Err(e) => {
+                            send_response!("info string position ignored: {} {} after {}", e, mv_str, game.board.to_fen());
+                            self.game.restore_search_tables(tables);
+                            return;
+                        }
                     }
                 }
+                if continues {
+                    self.send_debug(&format!("continuing the game with {} new moves, {} hash entries kept",
+                                             new_moves.len(), tables.transposition_table.len()));
+                    game.restore_search_tables(tables);
+                } else {
+                    self.send_debug("new game, search tables cleared");
+                    self.new_tables_generation();
+                }
+                self.game = game;
+                self.send_debug(&format!("position {}", self.game.board.to_fen()));
             }
-            UciCommand::Go { depth } => {
-                self.stop_signal.store(false, Ordering::Relaxed);
-                let game_clone = self.game.clone();
-                let depth = depth.unwrap_or(self.max_depth);
-                
-                self.search_sender.send(SearchTask::Go { 
-                    game: game_clone,
-                    depth,
-                    is_eval: self.is_evaluation_mode,
-                }).expect("Failed to send search task");
+            UciCommand::Go(params) => self.start_search(params),
+            UciCommand::PonderHit => {
+                // The opponent played the expected move, the ponder search becomes a normal timed search
+                if self.pondering.swap(false, Ordering::Relaxed) {
+                    let deadline = self.ponder_budget_ms.map_or(NO_DEADLINE, |ms| now_ms() + ms);
+                    self.deadline_ms.store(deadline, Ordering::Relaxed);
+                    self.hold.notify();
+                }
             }
             UciCommand::Stop => {
-                self.stop_signal.store(true, Ordering::Relaxed);
+                self.pondering.store(false, Ordering::Relaxed);
+                self.stop_search();
+            }
+            UciCommand::Bench { depth } => {
+                // Runs on this thread, with its own games, so the engine state is left alone
+                #[cfg(not(target_arch = "wasm32"))]
+                run_bench(depth.unwrap_or(BENCH_DEPTH));
+                #[cfg(target_arch = "wasm32")]
+                {
+                    let _ = depth;
+                    send_response!("info string bench is not supported in the browser");
+                }
+            }
+            UciCommand::Debug(enabled) => {
+                self.debug = enabled;
+            }
+            UciCommand::Register => {}
+            UciCommand::Display => {
+                let board = &self.game.board;
+                let checkers: Vec<String> = board.checkers().iter().map(|sq| sq.to_algebraic()).collect();
+                let mut legal_moves = Vec::new();
+                board.generate_legal_moves(&mut Vec::new(), &mut legal_moves);
+                let legal_moves: Vec<String> = legal_moves.iter().map(|mv| mv.to_uci()).collect();
+                send_response!("{}\nFen: {}\nKey: {:016X}\nCheckers: {}\nLegal moves ({}): {}", board, board.to_fen(),
+                               board.compute_zobrist_hash(), checkers.join(" "), legal_moves.len(), legal_moves.join(" "));
+            }
+            UciCommand::Flip => {
+                // The moves that led here do not apply to the mirrored position, it starts a game of its own
+                self.new_tables_generation();
+                self.game = self.new_game(self.game.board.mirrored());
             }
             UciCommand::Quit => {
                 self.search_sender.send(SearchTask::Quit).expect("Failed to send quit task");
@@ -216,4 +715,207 @@ impl Engine {
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Parses and runs one line of xboard input, errors are answered as the protocol asks.
+    pub fn process_xboard_line(&mut self, line: &str) {
+        match parse_xboard_command(line) {
+            Ok(command) => self.process_xboard_command(command),
+            Err(XboardParseError::MissingCommand) => {} // empty line
+            Err(XboardParseError::UnknownCommand(_)) => send_response!("Error (unknown command): {}", line.trim()),
+            Err(e) => send_response!("Error ({}): {}", e, line.trim()),
+        }
+    }
+
+    pub fn process_xboard_command(&mut self, command: XboardCommand) {
+        self.reclaim_search_tables(); // plays the move of the last search
+        match command {
+            XboardCommand::Xboard => {
+                self.xboard_state();
+            }
+            XboardCommand::Protover(_) => {
+                send_response!("feature myname=\"ChessFlow\" usermove=1 setboard=1 ping=1 colors=0 analyze=0 sigint=0 sigterm=0 done=1");
+            }
+            XboardCommand::New => {
+                self.stop_thinking();
+                self.game = self.new_game(Board::new_start_pos());
+                let xboard = self.xboard_state();
+                xboard.force = false; // the engine plays Black
+                xboard.depth = None;
+                xboard.move_time_ms = None;
+            }
+            XboardCommand::UserMove(mv) => match self.game.board.parse_uci_move(&mv) {
+                Ok(parsed) => {
+                    self.game.make_move(&parsed);
+                    if !self.xboard_state().force {
+                        self.start_search(self.xboard_go_params());
+                    }
+                }
+                Err(_) => send_response!("Illegal move: {}", mv),
+            },
+            XboardCommand::Go => {
+                self.xboard_state().force = false; // the engine plays the side to move from now on
+                self.start_search(self.xboard_go_params());
+            }
+            XboardCommand::Force => {
+                self.stop_thinking();
+                self.xboard_state().force = true;
+            }
+            XboardCommand::Level { moves_per_session, base_ms, increment_ms } => {
+                let xboard = self.xboard_state();
+                xboard.moves_per_session = moves_per_session;
+                xboard.base_ms = Some(base_ms);
+                xboard.increment_ms = increment_ms;
+                xboard.move_time_ms = None;
+            }
+            XboardCommand::St { move_time_ms } => self.xboard_state().move_time_ms = Some(move_time_ms),
+            XboardCommand::Sd { depth } => self.xboard_state().depth = Some(depth),
+            XboardCommand::Time { engine_ms } => self.xboard_state().engine_ms = Some(engine_ms),
+            XboardCommand::Otim { opponent_ms } => self.xboard_state().opponent_ms = Some(opponent_ms),
+            XboardCommand::Undo => {
+                self.stop_thinking();
+                self.take_back_moves(1);
+            }
+            XboardCommand::Remove => {
+                self.stop_thinking();
+                self.take_back_moves(2);
+            }
+            XboardCommand::SetBoard(fen) => match Board::from_fen(&fen) {
+                Ok(board) => {
+                    self.stop_thinking();
+                    self.game = self.new_game(board);
+                }
+                Err(e) => send_response!("tellusererror {}", e),
+            },
+            XboardCommand::Post => self.xboard_state().post = true,
+            XboardCommand::NoPost => self.xboard_state().post = false,
+            XboardCommand::Result(_) => {
+                // The game is over, wait for `new`
+                self.stop_thinking();
+                self.xboard_state().force = true;
+            }
+            XboardCommand::Ping(n) => send_response!("pong {}", n),
+            XboardCommand::MoveNow => self.stop_search(),
+            XboardCommand::Quit => self.process_command(UciCommand::Quit),
+            XboardCommand::Ignored => {}
+        }
+    }
+
+    fn xboard_state(&mut self) -> &mut XboardState {
+        self.xboard.get_or_insert_with(XboardState::default)
+    }
+
+    /// Stops the current search, it still answers with the best move found so far.
+    fn stop_search(&self) {
+        self.stop_signal.store(true, Ordering::Relaxed);
+        self.hold.notify();
+    }
+
+    /// Stops the current search without playing its move.
+    fn stop_thinking(&mut self) {
+        self.stop_search();
+        self.new_tables_generation();
+    }
+
+    /// Limits of the next xboard search, from `level`, `st`, `sd`, `time` and `otim`.
+    fn xboard_go_params(&self) -> GoParams {
+        let xboard = self.xboard.clone().unwrap_or_default();
+        let mut params = GoParams { depth: xboard.depth, ..GoParams::default() };
+        if let Some(move_time_ms) = xboard.move_time_ms {
+            params.movetime = Some(move_time_ms);
+        } else if let Some(base_ms) = xboard.base_ms {
+            let own = xboard.engine_ms.unwrap_or(base_ms);
+            let opponent = xboard.opponent_ms.unwrap_or(base_ms);
+            let (wtime, btime) = if self.game.board.turn == Color::White { (own, opponent) } else { (opponent, own) };
+            params.wtime = Some(wtime);
+            params.btime = Some(btime);
+            params.winc = Some(xboard.increment_ms);
+            params.binc = Some(xboard.increment_ms);
+            if xboard.moves_per_session > 0 {
+                let session = xboard.moves_per_session as u64;
+                params.movestogo = Some(session - (self.game.board.fullmove_number as u64 - 1) % session);
+            }
+        }
+        params
+    }
+
+    /// Replays the game without its last `count` moves, keeping the search tables.
+    fn take_back_moves(&mut self, count: usize) {
+        let keep = self.game.move_history.len().saturating_sub(count);
+        let moves = self.game.move_history[..keep].to_vec();
+        let tables = self.game.take_search_tables();
+        let mut game = self.new_game(self.game.start_board.clone());
+        for mv in &moves {
+            game.make_move(mv);
+        }
+        game.restore_search_tables(tables);
+        self.game = game;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal_states::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(&fen.parse::<Fen>().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reports_a_pawn_up_as_about_a_hundred_centipawns() {
+        for (fen, sign) in [
+            ("rnbqkbnr/ppp1pppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 1),
+            ("rnbqkbnr/ppp1pppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", -1),
+        ] {
+            let board = board(fen);
+            let mut legal_moves = Vec::new();
+            board.generate_legal_moves(&mut Vec::new(), &mut legal_moves);
+            let score = board.evaluate(0, &mut Vec::new(), &mut legal_moves, GameState::Ongoing, &EvalParams::default());
+            let cp = uci_score_cp(&board, score) * sign;
+            assert!((60..=160).contains(&cp), "{}: cp {}", fen, cp);
+            assert_eq!(uci_score(&board, score, 1), format!("cp {}", cp * sign));
+            assert_eq!(xboard_score(&board, score, 1), cp * sign);
+        }
+    }
+
+    #[test]
+    fn position_with_an_illegal_move_is_ignored() {
+        let position = |moves: &[&str]| UciCommand::Position { fen: None, moves: moves.iter().map(|mv| mv.to_string()).collect() };
+        let mut engine = Engine::new();
+        engine.process_command(position(&["e2e4", "e7e5"]));
+        let expected = engine.game.board.to_fen();
+        assert_eq!(engine.game.move_history.len(), 2);
+
+        engine.process_command(position(&["e2e4", "e7e5", "e1e3"]));
+        engine.process_command(position(&["e2e4", "e7e5", "g1f3", "e8e6"]));
+        engine.process_command(UciCommand::Position { fen: Some("4k3/8/8/8/8/8/8/4K2R w KQ - 0 1".parse().unwrap()), moves: Vec::new() });
+        assert_eq!(engine.game.board.to_fen(), expected);
+        assert_eq!(engine.game.move_history.len(), 2);
+
+        engine.process_command(position(&["e2e4", "e7e5", "g1f3"]));
+        assert_eq!(engine.game.move_history.len(), 3);
+    }
+
+    #[test]
+    fn setoption_applies_valid_values_and_keeps_the_setting_otherwise() {
+        let set = |engine: &mut Engine, name: &str, value: &str| {
+            engine.process_command(UciCommand::SetOption { name: name.to_string(), value: value.to_string() });
+        };
+        let mut engine = Engine::new();
+        set(&mut engine, "multipv", "3");
+        set(&mut engine, "Hash", "4");
+        set(&mut engine, "BookMoves", "best");
+        assert_eq!(engine.multi_pv, 3);
+        assert_eq!(engine.hash_mb, 4);
+        assert_eq!(engine.game.transposition_table.size(), tt_entries_for_mb(4));
+        assert!(engine.best_book_move);
+
+        set(&mut engine, "MultiPV", "17");
+        set(&mut engine, "Hash", "0");
+        set(&mut engine, "BookMoves", "Random");
+        set(&mut engine, "Threads", "4");
+        assert_eq!(engine.multi_pv, 3);
+        assert_eq!(engine.hash_mb, 4);
+        assert!(engine.best_book_move);
+    }
+}
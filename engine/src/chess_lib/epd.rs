@@ -0,0 +1,251 @@
+// Extended Position Description
+// see: https://www.chessprogramming.org/Extended_Position_Description
+//
+// "<pieces> <side> <castling> <en passant> [<opcode> [<operand>...];]..."
+// The move counters are not part of the position, they come from the `hmvc` and `fmvn` opcodes if present.
+// Operands are plain tokens or "quoted strings", moves in `bm`/`am` are in SAN.
+
+use crate::board::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    MissingFields,
+    InvalidPosition(String),
+    UnterminatedString,
+    InvalidOperation(String),
+    InvalidMove { opcode: String, error: SanError },
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpdError::MissingFields => write!(f, "EPD needs at least four fields"),
+            EpdError::InvalidPosition(e) => write!(f, "Invalid EPD position: {}", e),
+            EpdError::UnterminatedString => write!(f, "Unterminated string in EPD operation"),
+            EpdError::InvalidOperation(op) => write!(f, "Invalid EPD operation: {}", op),
+            EpdError::InvalidMove { opcode, error } => write!(f, "Invalid move in EPD {} operation: {}", opcode, error),
+        }
+    }
+}
+impl std::error::Error for EpdError {}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpdOperation {
+    pub opcode: String,
+    pub operands: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EpdRecord {
+    pub board: Board,
+    pub operations: Vec<EpdOperation>, // in line order
+}
+
+impl EpdRecord {
+    pub fn operation(&self, opcode: &str) -> Option<&EpdOperation> {
+        self.operations.iter().find(|op| op.opcode == opcode)
+    }
+
+    /// First operand of `opcode`.
+    pub fn operand(&self, opcode: &str) -> Option<&str> {
+        self.operation(opcode)?.operands.first().map(String::as_str)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operand("id")
+    }
+
+    /// Moves of the `bm` (best move) operation, empty if there is none.
+    pub fn best_moves(&self) -> Result<Vec<ChessMove>, EpdError> {
+        self.moves("bm")
+    }
+
+    /// Moves of the `am` (avoid move) operation, empty if there is none.
+    pub fn avoid_moves(&self) -> Result<Vec<ChessMove>, EpdError> {
+        self.moves("am")
+    }
+
+    fn moves(&self, opcode: &str) -> Result<Vec<ChessMove>, EpdError> {
+        let Some(op) = self.operation(opcode) else {
+            return Ok(Vec::new());
+        };
+        op.operands.iter()
+            .map(|san| self.board.parse_san(san).map_err(|error| EpdError::InvalidMove { opcode: opcode.to_string(), error }))
+            .collect()
+    }
+
+    pub fn to_epd(&self) -> String {
+        // The first four FEN fields
+        let fen = self.board.to_fen();
+        let mut epd = fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+        for op in &self.operations {
+            epd.push(' ');
+            epd.push_str(&op.opcode);
+            for operand in &op.operands {
+                if operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == ';' || c == '"') {
+                    epd.push_str(&format!(" \"{}\"", operand.replace('"', "'")));
+                } else {
+                    epd.push(' ');
+                    epd.push_str(operand);
+                }
+            }
+            epd.push(';');
+        }
+        epd
+    }
+}
+
+impl FromStr for EpdRecord {
+    type Err = EpdError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let mut fields = Vec::with_capacity(4);
+        let mut rest = line;
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(EpdError::MissingFields);
+            }
+            fields.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+
+        let operations = parse_operations(rest)?;
+        let counter = |opcode: &str, default: &str| {
+            operations.iter().find(|op| op.opcode == opcode).and_then(|op| op.operands.first()).cloned().unwrap_or_else(|| default.to_string())
+        };
+
+        let fen = format!("{} {} {}", fields.join(" "), counter("hmvc", "0"), counter("fmvn", "1"));
+        let fen = fen.parse::<Fen>().map_err(|e| EpdError::InvalidPosition(e.to_string()))?;
+        let board = Board::from_fen(&fen).map_err(|e| EpdError::InvalidPosition(e.to_string()))?;
+
+        Ok(EpdRecord { board, operations })
+    }
+}
+
+fn parse_operations(text: &str) -> Result<Vec<EpdOperation>, EpdError> {
+    let mut operations = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut opcode = String::new();
+        while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && c != ';' && c != '"') {
+            opcode.push(c);
+        }
+        if opcode.is_empty() {
+            return Err(EpdError::InvalidOperation(text.trim().to_string()));
+        }
+
+        // Operands up to the ';', which may be missing after the last operation
+        let mut operands = Vec::new();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.next() {
+                None | Some(';') => break,
+                Some('"') => {
+                    let mut operand = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => operand.push(c),
+                            None => return Err(EpdError::UnterminatedString),
+                        }
+                    }
+                    operands.push(operand);
+                }
+                Some(c) => {
+                    let mut operand = c.to_string();
+                    while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && c != ';') {
+                        operand.push(c);
+                    }
+                    operands.push(operand);
+                }
+            }
+        }
+        operations.push(EpdOperation { opcode, operands });
+    }
+    Ok(operations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(line: &str) -> EpdRecord {
+        line.parse().unwrap()
+    }
+
+    fn uci(moves: Vec<ChessMove>) -> Vec<String> {
+        moves.iter().map(ChessMove::to_uci).collect()
+    }
+
+    #[test]
+    fn parses_operations_and_quoted_operands() {
+        let epd = record("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id \"start; pos\"; c0 \"a b\" plain;acd 12; noop;");
+        let opcodes: Vec<&str> = epd.operations.iter().map(|op| op.opcode.as_str()).collect();
+        assert_eq!(opcodes, ["id", "c0", "acd", "noop"]);
+        assert_eq!(epd.id(), Some("start; pos"));
+        assert_eq!(epd.operation("c0").unwrap().operands, ["a b", "plain"]);
+        assert_eq!(epd.operand("acd"), Some("12"));
+        assert!(epd.operation("noop").unwrap().operands.is_empty());
+        assert_eq!(epd.operation("bm"), None);
+    }
+
+    #[test]
+    fn accepts_a_missing_final_semicolon() {
+        let epd = record("8/8/8/8/8/8/8/K1k5 w - - id last");
+        assert_eq!(epd.id(), Some("last"));
+    }
+
+    #[test]
+    fn takes_the_move_counters_from_hmvc_and_fmvn() {
+        let epd = record("8/8/8/8/8/8/8/K1k5 b - - hmvc 7; fmvn 42;");
+        assert_eq!(epd.board.to_fen(), "8/8/8/8/8/8/8/K1k5 b - - 7 42");
+        assert_eq!(record("8/8/8/8/8/8/8/K1k5 b - -").board.to_fen(), "8/8/8/8/8/8/8/K1k5 b - - 0 1");
+    }
+
+    #[test]
+    fn parses_best_and_avoid_moves_in_san() {
+        let epd = record("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5 Bc4; am Ng5;");
+        assert_eq!(uci(epd.best_moves().unwrap()), ["f1b5", "f1c4"]);
+        assert_eq!(uci(epd.avoid_moves().unwrap()), ["f3g5"]);
+
+        let none = record("8/8/8/8/8/8/8/K1k5 w - -");
+        assert!(none.best_moves().unwrap().is_empty());
+
+        let illegal = record("8/8/8/8/8/8/8/K1k5 w - - bm Qh5;");
+        assert!(matches!(illegal.best_moves(), Err(EpdError::InvalidMove { opcode, .. }) if opcode == "bm"));
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        let error = |line: &str| line.parse::<EpdRecord>().unwrap_err();
+        assert_eq!(error("8/8/8/8/8/8/8/K1k5 w -"), EpdError::MissingFields);
+        assert_eq!(error("8/8/8/8/8/8/8/K1k5 w - - id \"open;"), EpdError::UnterminatedString);
+        assert_eq!(error("8/8/8/8/8/8/8/K1k5 w - - ;"), EpdError::InvalidOperation(";".to_string()));
+        assert!(matches!(error("8/8/8/8/8/8/8/K1k5 x - - id a;"), EpdError::InvalidPosition(_)));
+    }
+
+    #[test]
+    fn writes_records_back() {
+        let line = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5; id \"Ruy Lopez\"; c0 \"\"; acd 3;";
+        assert_eq!(record(line).to_epd(), line);
+
+        let mut epd = record("8/8/8/8/8/8/8/K1k5 w - -");
+        epd.operations.push(EpdOperation { opcode: "c0".to_string(), operands: vec!["say \"hi\"".to_string()] });
+        assert_eq!(epd.to_epd(), "8/8/8/8/8/8/8/K1k5 w - - c0 \"say 'hi'\";");
+        assert_eq!(record(&epd.to_epd()).operand("c0"), Some("say 'hi'"));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::simple_pst::*;
+
+use serde::{Deserialize, Serialize};
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+use std::fmt;
+
+/// Every tunable weight read by `Board::evaluate`.
+/// The defaults reproduce the original hard-coded evaluation, so a file only needs the fields it changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalParams {
+    pub pawn_value: i32,
+    pub knight_value: i32,
+    pub bishop_value: i32,
+    pub rook_value: i32,
+    pub queen_value: i32,
+
+    // Piece-square tables are indexed by square (a1 = 0) for White, Black reads the entry at square ^ 56 (ranks flipped)
+    #[serde(with = "pst_serde")]
+    pub pawn_pst: [i32; 64],
+    #[serde(with = "pst_serde")]
+    pub knight_pst: [i32; 64],
+    #[serde(with = "pst_serde")]
+    pub bishop_pst: [i32; 64],
+    #[serde(with = "pst_serde")]
+    pub rook_pst: [i32; 64],
+    #[serde(with = "pst_serde")]
+    pub queen_pst: [i32; 64],
+
+    /// Bonus for a passed pawn, indexed by the rank relative to its owner (0 = own back rank).
+    pub passed_pawn_bonus: [i32; 8],
+
+    /// Multiplier for the difference in legal move counts.
+    pub mobility_weight: i32,
+
+    pub pawn_shield_bonus: i32,
+    pub king_zone_attack_penalty: i32,
+
+    /// Both sides having less non-king material than this switches on the endgame king terms.
+    pub endgame_material_threshold: i32,
+    pub king_centralization_bonus: i32,
+    pub king_edge_penalty: i32,
+
+    /// Bonus on top of the material for endgames that are known wins (see endgame.rs).
+    pub known_win_bonus: i32,
+    /// Per step the losing king is away from the center.
+    pub push_to_edge_weight: i32,
+    /// Per step the kings are closer than the maximum distance.
+    pub push_close_weight: i32,
+    /// Per step the losing king is closer to the mating corner in KBNK.
+    pub push_to_corner_weight: i32,
+
+    /// Scale factors out of `SCALE_FACTOR_NORMAL` for drawish material configurations.
+    pub opposite_bishops_scale: i32,
+    pub pawnless_scale: i32,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams {
+            pawn_value: 100,
+            knight_value: 320,
+            bishop_value: 330,
+            rook_value: 500,
+            queen_value: 900,
+
+            pawn_pst: PAWN_PST,
+            knight_pst: KNIGHT_PST,
+            bishop_pst: BISHOP_PST,
+            rook_pst: ROOK_PST,
+            queen_pst: QUEEN_PST,
+
+            passed_pawn_bonus: [0, 0, 10, 20, 40, 70, 120, 0],
+
+            mobility_weight: 2,
+
+            pawn_shield_bonus: 15,
+            king_zone_attack_penalty: 10,
+
+            endgame_material_threshold: 900 + 500, // no queen and not too much other material
+            king_centralization_bonus: 10,
+            king_edge_penalty: -15,
+
+            known_win_bonus: 10_000,
+            push_to_edge_weight: 20,
+            push_close_weight: 10,
+            push_to_corner_weight: 40,
+
+            opposite_bishops_scale: 16,
+            pawnless_scale: 8,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalParamsError {
+    Io(String),
+    InvalidUtf8,
+    Json(String),
+    Toml(String),
+}
+
+impl fmt::Display for EvalParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalParamsError::Io(e) => write!(f, "Could not read evaluation file: {}", e),
+            EvalParamsError::InvalidUtf8 => write!(f, "Evaluation parameters are not valid UTF-8"),
+            EvalParamsError::Json(e) => write!(f, "Invalid JSON evaluation parameters: {}", e),
+            EvalParamsError::Toml(e) => write!(f, "Invalid TOML evaluation parameters: {}", e),
+        }
+    }
+}
+impl std::error::Error for EvalParamsError {}
+
+
+impl EvalParams {
+    pub fn from_json_str(s: &str) -> Result<Self, EvalParamsError> {
+        serde_json::from_str(s).map_err(|e| EvalParamsError::Json(e.to_string()))
+    }
+
+    pub fn from_toml_str(s: &str) -> Result<Self, EvalParamsError> {
+        toml::from_str(s).map_err(|e| EvalParamsError::Toml(e.to_string()))
+    }
+
+    /// Parses a buffer holding either format. JSON always starts with '{', which TOML never does.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EvalParamsError> {
+        let s = std::str::from_utf8(bytes).map_err(|_| EvalParamsError::InvalidUtf8)?;
+        if s.trim_start().starts_with('{') {
+            Self::from_json_str(s)
+        } else {
+            Self::from_toml_str(s)
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_file(path: &str) -> Result<Self, EvalParamsError> {
+        let bytes = std::fs::read(path).map_err(|e| EvalParamsError::Io(e.to_string()))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+
+// Shared by every search thread. Replaced wholesale when a new file is loaded,
+// so a search in progress keeps using the parameters it started with (see `Game::eval_params`).
+static EVAL_PARAMS: Lazy<RwLock<Arc<EvalParams>>> = Lazy::new(|| RwLock::new(Arc::new(EvalParams::default())));
+
+pub fn eval_params() -> Arc<EvalParams> {
+    Arc::clone(&EVAL_PARAMS.read().unwrap())
+}
+
+pub fn set_eval_params(params: EvalParams) {
+    *EVAL_PARAMS.write().unwrap() = Arc::new(params);
+}
+
+
+// serde only implements arrays up to 32 elements, so the 64 entry tables go through a Vec
+mod pst_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(table: &[i32; 64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(table.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[i32; 64], D::Error> {
+        let values = Vec::<i32>::deserialize(deserializer)?;
+        let len = values.len();
+        values.try_into().map_err(|_| D::Error::custom(format!("expected 64 table entries, found {}", len)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_json_and_toml_keeping_missing_fields_at_their_default() {
+        let expected = EvalParams { queen_value: 950, mobility_weight: 3, ..EvalParams::default() };
+        assert_eq!(EvalParams::from_bytes(b"queen_value = 950\nmobility_weight = 3\n"), Ok(expected.clone()));
+        assert_eq!(EvalParams::from_bytes(b"  {\"queen_value\": 950, \"mobility_weight\": 3}"), Ok(expected));
+        assert_eq!(EvalParams::from_bytes(b""), Ok(EvalParams::default()));
+    }
+
+    #[test]
+    fn round_trips_the_piece_square_tables() {
+        let mut params = EvalParams::default();
+        params.knight_pst[27] = 99;
+        let json = serde_json::to_string(&params).unwrap();
+        assert_eq!(EvalParams::from_json_str(&json), Ok(params.clone()));
+        let toml = toml::to_string(&params).unwrap();
+        assert_eq!(EvalParams::from_toml_str(&toml), Ok(params));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        assert_eq!(EvalParams::from_bytes(&[0xff, 0xfe]), Err(EvalParamsError::InvalidUtf8));
+        assert!(matches!(EvalParams::from_bytes(b"{\"pawn_value\": \"many\"}"), Err(EvalParamsError::Json(_))));
+        assert!(matches!(EvalParams::from_bytes(b"pawn_value = "), Err(EvalParamsError::Toml(_))));
+        assert!(matches!(EvalParams::from_bytes(b"pawn_pst = [1, 2, 3]"), Err(EvalParamsError::Toml(e)) if e.contains("expected 64 table entries, found 3")));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn reports_missing_files() {
+        assert!(matches!(EvalParams::from_file("/nonexistent/params.toml"), Err(EvalParamsError::Io(_))));
+    }
+}
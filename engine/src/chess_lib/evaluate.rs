@@ -1,25 +1,35 @@
 use crate::board::*;
 use crate::terminal_states::*;
-use crate::simple_pst::*;
+use crate::eval_params::*;
+use crate::endgame::*;
+
+/// Score of a checkmate from White's perspective. The depth left when it is found is added, so shorter mates score higher.
+pub const MATE_SCORE: i32 = 10_000_000;
+
+/// Moves until mate for a score returned by a root search of `depth` plies, positive if White mates.
+/// `None` if the score is not a mate score.
+pub fn mate_distance(score: i32, depth: u8) -> Option<i32> {
+    let remaining_depth = score.abs() - MATE_SCORE;
+    if !(0..=u8::MAX as i32).contains(&remaining_depth) {
+        return None;
+    }
+    let plies = (depth as i32 - remaining_depth).max(1);
+    let moves = (plies + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
 
 impl Board {
-    /// Evaluates the current board from White’s perspective
+    /// Evaluates the current board from White’s perspective with the weights in `params`
+    /// (searches use the set they picked up when they started, see `Game::eval_params`)
     #[cfg_attr(feature = "tracy", tracing::instrument(skip_all))]
-    pub fn evaluate(&self, remaining_depth: u8, pseudo_legal_moves: &mut Vec<ChessMove>, legal_moves: &mut Vec<ChessMove>, game_state : GameState) -> i32 {
-        const PAWN_VALUE: i32   = 100;
-        const KNIGHT_VALUE: i32 = 320;
-        const BISHOP_VALUE: i32 = 330;
-        const ROOK_VALUE: i32   = 500;
-        const QUEEN_VALUE: i32  = 900;
-        const MATE_SCORE: i32   = 10_000_000;
-
+    pub fn evaluate(&self, remaining_depth: u8, pseudo_legal_moves: &mut Vec<ChessMove>, legal_moves: &mut Vec<ChessMove>, game_state : GameState, params: &EvalParams) -> i32 {
         // Fetch terminal-state first
         match game_state {
             GameState::Checkmate(checkmated) => {
-                if checkmated == self.turn {
-                    return -MATE_SCORE + remaining_depth as i32;
+                if checkmated == Color::White {
+                    return -MATE_SCORE - remaining_depth as i32;
                 } else {
-                    return MATE_SCORE - remaining_depth as i32;
+                    return MATE_SCORE + remaining_depth as i32;
                 }
             }
             GameState::Stalemate
@@ -29,14 +39,19 @@ impl Board {
             GameState::Ongoing => {} // fall through
         }
 
+        let signature = MaterialSignature::of(self);
+        if let Some(score) = self.evaluate_endgame(&signature, params) {
+            return score;
+        }
+
         // MATERIAL + PST
         let mut score = 0;
         for &(pt, val, pst) in &[
-            (PieceType::Pawn,   PAWN_VALUE,   &PAWN_PST),
-            (PieceType::Knight, KNIGHT_VALUE, &KNIGHT_PST),
-            (PieceType::Bishop, BISHOP_VALUE, &BISHOP_PST),
-            (PieceType::Rook,   ROOK_VALUE,   &ROOK_PST),
-            (PieceType::Queen,  QUEEN_VALUE,  &QUEEN_PST),
+            (PieceType::Pawn,   params.pawn_value,   &params.pawn_pst),
+            (PieceType::Knight, params.knight_value, &params.knight_pst),
+            (PieceType::Bishop, params.bishop_value, &params.bishop_pst),
+            (PieceType::Rook,   params.rook_value,   &params.rook_pst),
+            (PieceType::Queen,  params.queen_value,  &params.queen_pst),
         ] {
             let bb = self.piece_bbs[pt as usize];
 
@@ -47,28 +62,23 @@ impl Board {
 
             let bbb = bb & self.color_bbs[Color::Black as usize];
             for sq in bbb.iter() {
-                score -= val + pst.get(63 - sq as usize).unwrap();
+                score -= val + pst.get(sq as usize ^ 56).unwrap(); // the same rank seen from Black's side
             }
         }
 
-        const PASSED_PAWN_BASE_BONUS: [i32; 8] = [0, 5, 15, 30, 50, 75, 100, 0]; // Bonus by rank (0 for rank 0/7 as it's not advanced)
-                                                                                // Or for white: [0(1st), 5(2nd), 10(3rd), 20(4th), 40(5th), 70(6th), 120(7th), 0(8th-promo)]
-                                                                                // Let's use a more common indexing for white pawns (rank 1 is index 1, rank 6 is index 6 for 7th rank)
-        const WHITE_PASSED_PAWN_BONUS_BY_RANK: [i32; 8] = [0, 0, 10, 20, 40, 70, 120, 0]; // Index is rank (0-7), bonus for ranks 2-7 (actual ranks 3-8)
-        const BLACK_PASSED_PAWN_BONUS_BY_RANK: [i32; 8] = [0, 120, 70, 40, 20, 10, 0, 0]; // Index is rank (0-7), bonus for ranks 0-5 (actual ranks 1-6)
-
+        // Passed pawn bonus is indexed by the rank relative to the pawn's owner
 
         let white_pawns_bb = self.piece_bbs[PieceType::Pawn as usize] & self.color_bbs[Color::White as usize];
         for sq in white_pawns_bb.iter() {
             if self.is_passed_pawn(sq, Color::White) { // Assuming you add is_passed_pawn to Board
-                score += WHITE_PASSED_PAWN_BONUS_BY_RANK[sq.rank() as usize];
+                score += params.passed_pawn_bonus[sq.rank() as usize];
             }
         }
 
         let black_pawns_bb = self.piece_bbs[PieceType::Pawn as usize] & self.color_bbs[Color::Black as usize];
         for sq in black_pawns_bb.iter() {
             if self.is_passed_pawn(sq, Color::Black) {
-                score -= BLACK_PASSED_PAWN_BONUS_BY_RANK[sq.rank() as usize]; // Subtract for black's advantage
+                score -= params.passed_pawn_bonus[7 - sq.rank() as usize]; // Subtract for black's advantage
             }
         }
 
@@ -81,7 +91,8 @@ impl Board {
             flipped.generate_legal_moves(pseudo_legal_moves, legal_moves);
             legal_moves.len() as i32
         };
-        score += (my_moves - opp_moves) * 2;
+        let mobility = (my_moves - opp_moves) * params.mobility_weight; // for the side to move
+        score += if self.turn == Color::White { mobility } else { -mobility };
 
         // SYMMETRIC KING SAFETY (always from White's perspective)
         let mut white_king_safety = 0;
@@ -98,7 +109,7 @@ impl Board {
                 if let Some(sq) = king_sq.try_offset(*f, *r) {
                     if self.piece_bbs[PieceType::Pawn as usize].is_set(sq) && 
                        self.color_bbs[Color::White as usize].is_set(sq) {
-                        white_king_safety += 15;
+                        white_king_safety += params.pawn_shield_bonus;
                     }
                 }
             }
@@ -107,7 +118,7 @@ impl Board {
             let attacked_by_black = self.get_attacked_squares(Color::Black);
             for sq in king_sq.surrounding_squares() {
                 if attacked_by_black.is_set(sq) {
-                    white_king_safety -= 10;
+                    white_king_safety -= params.king_zone_attack_penalty;
                 }
             }
         }
@@ -123,7 +134,7 @@ impl Board {
                 if let Some(sq) = king_sq.try_offset(*f, *r) {
                     if self.piece_bbs[PieceType::Pawn as usize].is_set(sq) && 
                        self.color_bbs[Color::Black as usize].is_set(sq) {
-                        black_king_safety += 15;
+                        black_king_safety += params.pawn_shield_bonus;
                     }
                 }
             }
@@ -132,7 +143,7 @@ impl Board {
             let attacked_by_white = self.get_attacked_squares(Color::White);
             for sq in king_sq.surrounding_squares() {
                 if attacked_by_white.is_set(sq) {
-                    black_king_safety -= 10;
+                    black_king_safety -= params.king_zone_attack_penalty;
                 }
             }
         }
@@ -146,55 +157,57 @@ impl Board {
         // --- Determine Game Phase (simplified) ---
         let mut total_material_no_kings_white = 0;
         let mut total_material_no_kings_black = 0;
-        // (Assuming you have these constants defined elsewhere)
-        // const PAWN_VALUE: i32   = 100; etc.
         for piece_type_val in [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen] {
             let piece_val = match piece_type_val {
-                PieceType::Pawn => PAWN_VALUE,
-                PieceType::Knight => KNIGHT_VALUE,
-                PieceType::Bishop => BISHOP_VALUE,
-                PieceType::Rook => ROOK_VALUE,
-                PieceType::Queen => QUEEN_VALUE,
+                PieceType::Pawn => params.pawn_value,
+                PieceType::Knight => params.knight_value,
+                PieceType::Bishop => params.bishop_value,
+                PieceType::Rook => params.rook_value,
+                PieceType::Queen => params.queen_value,
                 _ => 0,
             };
             total_material_no_kings_white += (self.piece_bbs[piece_type_val as usize] & self.color_bbs[Color::White as usize]).popcount() as i32 * piece_val;
             total_material_no_kings_black += (self.piece_bbs[piece_type_val as usize] & self.color_bbs[Color::Black as usize]).popcount() as i32 * piece_val;
         }
 
-        let is_endgame = total_material_no_kings_white < (QUEEN_VALUE + ROOK_VALUE) && // Example threshold: no queen and not too much other material
-                        total_material_no_kings_black < (QUEEN_VALUE + ROOK_VALUE);
+        let is_endgame = total_material_no_kings_white < params.endgame_material_threshold &&
+                        total_material_no_kings_black < params.endgame_material_threshold;
         // Or, a simpler one: no queens on board.
         // let no_queens = (self.piece_bbs[PieceType::Queen as usize]).is_empty();
         // let is_endgame = no_queens;
 
 
         if is_endgame {
-            const KING_CENTRALIZATION_BONUS: i32 = 10; // Max bonus for being in the very center
-            const KING_EDGE_PENALTY: i32 = -15; // Penalty for king on edge in endgame
+            let king_centralization_bonus = params.king_centralization_bonus; // Max bonus for being in the very center
+            let king_edge_penalty = params.king_edge_penalty; // Penalty for king on edge in endgame
 
             // White King
             if let Some(king_sq) = self.find_king_square(Color::White) {
                 let r = king_sq.rank() as i32; // 0-7
                 let f = king_sq.file() as i32; // 0-7
                 // Bonus for centralization (distance from edges)
-                score += (3 - (3 - f).abs() + 3 - (3 - r).abs()) * (KING_CENTRALIZATION_BONUS / 6); // Max 10 at center
+                score += (f.min(7 - f) + r.min(7 - r)) * (king_centralization_bonus / 6); // Max 10 at center
 
                 if r == 0 || r == 7 || f == 0 || f == 7 { // King on edge
-                    score += KING_EDGE_PENALTY;
+                    score += king_edge_penalty;
                 }
             }
             // Black King
             if let Some(king_sq) = self.find_king_square(Color::Black) {
                 let r = king_sq.rank() as i32;
                 let f = king_sq.file() as i32;
-                score -= (3 - (3 - f).abs() + 3 - (3 - r).abs()) * (KING_CENTRALIZATION_BONUS / 6);
+                score -= (f.min(7 - f) + r.min(7 - r)) * (king_centralization_bonus / 6);
 
                 if r == 0 || r == 7 || f == 0 || f == 7 {
-                    score -= KING_EDGE_PENALTY; // This becomes a bonus for white if black king is on edge
+                    score -= king_edge_penalty; // This becomes a bonus for white if black king is on edge
                 }
             }
         }
 
+        // Shrink the score in configurations that are hard to win for the side ahead
+        let strong = if score > 0 { Color::White } else { Color::Black };
+        score = score * self.endgame_scale_factor(&signature, strong, params) / SCALE_FACTOR_NORMAL;
+
         // Orient final score from White's perspective
         score
     }
@@ -233,3 +246,41 @@ impl Board {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(fen: &str, params: &EvalParams) -> i32 {
+        let board = Board::from_fen(&fen.parse::<Fen>().unwrap()).unwrap();
+        let mut legal_moves = Vec::new();
+        board.generate_legal_moves(&mut Vec::new(), &mut legal_moves);
+        board.evaluate(0, &mut Vec::new(), &mut legal_moves, GameState::Ongoing, params)
+    }
+
+    #[test]
+    fn default_params_give_the_reference_scores() {
+        // Catches unintended changes to the default evaluation, the start position is level
+        let params = EvalParams::default();
+        for (fen, score) in [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 0),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10", 125),
+            ("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3", 29),
+            ("2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1", -172),
+            ("r1b2rk1/2q1b1pp/p2ppn2/1p6/3QP3/1BN1B3/PPP3PP/R4RK1 w - - 0 1", 159),
+            ("4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19", 24),
+            ("6k1/5ppp/8/3P4/8/8/5PPP/3R2K1 w - - 0 1", 678),
+        ] {
+            assert_eq!(evaluate(fen, &params), score, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn uses_the_params_it_is_given() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let mut params = EvalParams::default();
+        let default_score = evaluate(fen, &params);
+        params.pawn_value += 50;
+        assert_eq!(evaluate(fen, &params), default_score + 50);
+    }
+}
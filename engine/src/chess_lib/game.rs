@@ -1,5 +1,10 @@
 use crate::board::*;
 use crate::terminal_states::*;
+use crate::eval_params::*;
+use crate::nnue::*;
+use crate::syzygy::*;
+use crate::dtm::*;
+use crate::time_manager::*;
 
 use std::collections::HashMap;
 
@@ -20,23 +25,125 @@ pub struct TTEntry {
     pub depth: u8,
     pub node_type: NodeType,
     pub best_move: Option<ChessMove>, // Store the best move found for this position
-    // hash_key: u64, // Can be added if using a fixed-size array for TT and need to check for collisions
+}
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 1024;
+
+/// Transposition table slots that fit in `mb` megabytes.
+pub fn tt_entries_for_mb(mb: usize) -> usize {
+    (mb * 1024 * 1024 / std::mem::size_of::<Option<(u64, TTEntry)>>()).max(1)
+}
+
+/// Fixed-size transposition table: the Zobrist key of a position picks its slot, and a store replaces whatever
+/// was in that slot. The slots are allocated by the first store, so games that never search cost nothing.
+#[derive(Debug, Clone, Default)]
+pub struct TranspositionTable {
+    slots: Vec<Option<(u64, TTEntry)>>, // empty until the first store, then `size` slots
+    size: usize,
+    len: usize, // occupied slots
+}
+
+impl TranspositionTable {
+    pub fn new(size: usize) -> Self {
+        TranspositionTable { slots: Vec::new(), size, len: 0 }
+    }
+
+    /// Number of slots, allocated or not.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of occupied slots.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn slot(&self, key: u64) -> usize {
+        ((key as u128 * self.size as u128) >> 64) as usize
+    }
+
+    #[inline]
+    pub fn get(&self, key: u64) -> Option<&TTEntry> {
+        match self.slots.get(self.slot(key)) {
+            Some(Some((stored, entry))) if *stored == key => Some(entry),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn insert(&mut self, key: u64, entry: TTEntry) {
+        if self.slots.is_empty() {
+            if self.size == 0 {
+                return;
+            }
+            self.slots = vec![None; self.size];
+        }
+        let slot = self.slot(key);
+        if self.slots[slot].is_none() {
+            self.len += 1;
+        }
+        self.slots[slot] = Some((key, entry));
+    }
+
+    /// Empties the table and frees its slots until the next store.
+    pub fn clear(&mut self) {
+        self.slots = Vec::new();
+        self.len = 0;
+    }
+
+    /// Changes the number of slots, which empties the table unless the size stays the same.
+    pub fn resize(&mut self, size: usize) {
+        if size != self.size {
+            self.clear();
+            self.size = size;
+        }
+    }
+}
+
+/// What searches learned about a game, kept from one move to the next (see `Game::take_search_tables`).
+#[derive(Debug, Clone)]
+pub struct SearchTables {
+    pub transposition_table: TranspositionTable,
+    pub killer_moves: Vec<[Option<ChessMove>; 2]>,
+    pub history_moves: [[i32; 64]; 64],
 }
 
 /// The main chess engine struct containing search state and tables.
 #[derive(Debug, Clone)]
 pub struct Game {
     pub board: Board,
+    pub start_board: Board, // position the game started from, `move_history` is replayed from here
     pub move_history: Vec<ChessMove>,
+    pub move_annotations: Vec<Option<MoveAnnotation>>, // one per move in `move_history`
+    pub pgn_tags: PgnTags,
     pub pseudo_legal_moves_container: Vec<Vec<ChessMove>>,
     pub legal_moves_container: Vec<Vec<ChessMove>>,
     pub board_repetition_counts: HashMap<u64, u8>,
-    pub transposition_table: HashMap<u64, TTEntry>,
+    pub transposition_table: TranspositionTable,
     pub killer_moves: Vec<[Option<ChessMove>; 2]>, // Two killer moves per depth
     pub history_moves: [[i32; 64]; 64], // History table for non-capture moves (from_square_index, to_square_index) -> score
     pub max_search_depth: u8,
     pub q_search_max_ply: u8,
     pub stop_signal: Arc<AtomicBool>,
+    pub nodes: u64, // nodes visited by alphabeta and qsearch, reset by the caller
+    pub node_limit: Option<u64>, // the search stops once `nodes` reaches this
+    pub report_progress: bool, // send `info` lines with the search progress (see `send_progress`), for the UCI engine
+    pub search_start_ms: u64, // `now_ms` when the current search started, for the progress lines
+    pub last_progress_ms: u64,
+    pub search_moves: Vec<ChessMove>, // root moves the search is restricted to (`go searchmoves`), all if empty
+    pub deadline_ms: Arc<AtomicU64>, // `now_ms` at which the search stops, `NO_DEADLINE` if none; shared so `ponderhit` can set it
+    pub eval_params: Arc<EvalParams>, // weights of the handcrafted evaluation for the current search
+    pub use_loaded_eval_params: bool, // pick up the loaded `eval_params()` when a search starts, off for the bench
+    pub use_nnue: bool,
+    pub nnue_network: Option<Arc<Network>>, // network used by the current search, None means handcrafted eval
+    pub nnue_stack: Vec<Accumulator>, // one accumulator per ply, reused between searches
+    pub nnue_ply: usize,
+    pub use_tablebases: bool, // probe the loaded Syzygy and DTM tables, off for the bench
+    pub syzygy: Option<Arc<Tablebases>>, // tables used by the current search
+    pub dtm_tables: Option<Arc<DtmTables>>, // own distance-to-mate tables used by the current search
+    pub tb_hits: u64, // successful tablebase probes, reset by the caller like `nodes`
 }
 
 impl Game {
@@ -52,30 +159,94 @@ impl Game {
 
 
         Game {
+            start_board: board.clone(),
             board,
             move_history: Vec::with_capacity(100),
+            move_annotations: Vec::with_capacity(100),
+            pgn_tags: PgnTags::default(),
             pseudo_legal_moves_container,
             legal_moves_container,
             board_repetition_counts: HashMap::new(),
             max_search_depth: max_depth,
-            transposition_table: HashMap::new(),
+            transposition_table: TranspositionTable::new(tt_entries_for_mb(DEFAULT_HASH_MB)),
             killer_moves: killer_moves,
             history_moves: [[0; 64]; 64], // Initialize history table
             q_search_max_ply,
-            stop_signal
+            stop_signal,
+            nodes: 0,
+            node_limit: None,
+            report_progress: false,
+            search_start_ms: 0,
+            last_progress_ms: 0,
+            search_moves: Vec::new(),
+            deadline_ms: Arc::new(AtomicU64::new(NO_DEADLINE)),
+            eval_params: eval_params(),
+            use_loaded_eval_params: true,
+            use_nnue: false,
+            nnue_network: None,
+            nnue_stack: Vec::new(),
+            nnue_ply: 0,
+            use_tablebases: true,
+            syzygy: None,
+            dtm_tables: None,
+            tb_hits: 0,
+        }
+    }
+
+    /// Forgets what earlier searches learned: transposition table, killer moves and history.
+    pub fn clear_search_tables(&mut self) {
+        self.transposition_table.clear();
+        self.killer_moves.iter_mut().for_each(|killers| *killers = [None; 2]);
+        self.history_moves = [[0; 64]; 64];
+    }
+
+    /// Sets the size of the transposition table, which clears it if the size changes.
+    pub fn set_hash_size(&mut self, mb: usize) {
+        self.transposition_table.resize(tt_entries_for_mb(mb));
+    }
+
+    /// Moves the search tables out of the game, leaving empty ones, e.g. to hand them to another thread without copying.
+    pub fn take_search_tables(&mut self) -> SearchTables {
+        let depths = self.killer_moves.len();
+        let tt_size = self.transposition_table.size();
+        SearchTables {
+            transposition_table: std::mem::replace(&mut self.transposition_table, TranspositionTable::new(tt_size)),
+            killer_moves: std::mem::replace(&mut self.killer_moves, vec![[None; 2]; depths]),
+            history_moves: std::mem::replace(&mut self.history_moves, [[0; 64]; 64]),
         }
     }
 
+    /// Puts back tables from `take_search_tables`, the killer moves are resized to the game's search depth.
+    /// A transposition table of another size than the game's is dropped.
+    pub fn restore_search_tables(&mut self, tables: SearchTables) {
+        let size = self.transposition_table.size();
+        self.transposition_table = tables.transposition_table;
+        self.transposition_table.resize(size);
+        self.killer_moves = tables.killer_moves;
+        self.killer_moves.resize(self.max_search_depth as usize + 1, [None; 2]);
+        self.history_moves = tables.history_moves;
+    }
+
+    /// Resizes the per-depth containers so searches up to `max_depth` are possible.
+    pub fn set_max_search_depth(&mut self, max_depth: u8) {
+        let len = max_depth as usize + 1;
+        self.pseudo_legal_moves_container.resize_with(len, Vec::new);
+        self.legal_moves_container.resize_with(len, Vec::new);
+        self.killer_moves.resize(len, [None; 2]);
+        self.max_search_depth = max_depth;
+    }
+
     pub fn make_move(&mut self, mv: &ChessMove) {
         self.board = self.board.make_move(&mv);
         self.move_history.push(*mv);
+        self.move_annotations.push(None);
         *self.board_repetition_counts.entry(self.board.compute_zobrist_hash()).or_insert(0) += 1;
     }
 
     pub fn print(&mut self) {
         // let board_hash = self.board.compute_zobrist_hash();
         // println!("={} {} {}", *self.board_repetition_counts.entry(board_hash).or_insert(0), board_hash, self.board.to_fen());
-        println!("{}", self.board);
+        println!("{:#}", self.board);
     }
 
     pub fn get_game_state(&mut self) -> GameState {
@@ -106,43 +277,252 @@ impl Game {
 
 
 use std::fmt::Write;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicU64};
 use std::sync::Arc;
 
+const PGN_LINE_WIDTH: usize = 80;
+
+/// Tag values written by `Game::to_pgn`. The seven tag roster is always written ("?" when unknown),
+/// `time_control` and `termination` only when set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnTags {
+    pub event: String,
+    pub site: String,
+    pub date: String, // YYYY.MM.DD, unknown parts as ??
+    pub round: String,
+    pub white: String,
+    pub black: String,
+    pub time_control: Option<String>, // e.g. "300+2"
+    pub termination: Option<String>,  // e.g. "normal", "time forfeit", "adjudication"
+    pub extra: Vec<(String, String)>, // any other tags (WhiteElo, ECO, ...), in file order
+}
+
+impl Default for PgnTags {
+    fn default() -> Self {
+        PgnTags {
+            event: "?".to_string(),
+            site: "?".to_string(),
+            date: "????.??.??".to_string(),
+            round: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+            time_control: None,
+            termination: None,
+            extra: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgnEval {
+    Centipawns(i32), // from White's perspective
+    Mate(i32),       // moves until mate, negative when Black mates
+}
+
+/// Comment written after a move, with the `[%eval]` and `[%clk]` commands used by most GUIs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveAnnotation {
+    pub comment: Option<String>,
+    pub eval: Option<PgnEval>,
+    pub clock_ms: Option<u64>, // remaining time of the side that moved
+}
+
+impl MoveAnnotation {
+    /// Reads `[%eval ...]` and `[%clk ...]` out of a PGN comment, the rest stays the comment text.
+    pub fn from_comment(text: &str) -> Self {
+        let mut annotation = MoveAnnotation::default();
+        let mut rest = String::new();
+        let mut remaining = text;
+        while let Some(start) = remaining.find("[%") {
+            rest.push_str(&remaining[..start]);
+            let Some(len) = remaining[start..].find(']') else {
+                remaining = &remaining[start..];
+                break;
+            };
+            let command = &remaining[start + 2..start + len];
+            match command.split_once(' ').map(|(name, value)| (name, value.trim())) {
+                Some(("eval", value)) => annotation.eval = match value.strip_prefix('#') {
+                    Some(mate) => mate.parse().ok().map(PgnEval::Mate),
+                    None => value.parse::<f64>().ok().map(|pawns| PgnEval::Centipawns((pawns * 100.0).round() as i32)),
+                },
+                Some(("clk", value)) => annotation.clock_ms = parse_clock(value),
+                _ => rest.push_str(&remaining[start..start + len + 1]),
+            }
+            remaining = &remaining[start + len + 1..];
+        }
+        rest.push_str(remaining);
+
+        let rest = rest.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !rest.is_empty() {
+            annotation.comment = Some(rest);
+        }
+        annotation
+    }
+
+    /// The text between the braces, `None` if there is nothing to write.
+    pub fn to_comment(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        match self.eval {
+            Some(PgnEval::Centipawns(cp)) => parts.push(format!("[%eval {:.2}]", cp as f64 / 100.0)),
+            Some(PgnEval::Mate(moves)) => parts.push(format!("[%eval #{}]", moves)),
+            None => {}
+        }
+        if let Some(ms) = self.clock_ms {
+            let seconds = ms / 1000;
+            parts.push(format!("[%clk {}:{:02}:{:02}]", seconds / 3600, seconds / 60 % 60, seconds % 60));
+        }
+        if let Some(comment) = &self.comment {
+            parts.push(comment.replace('}', ")"));
+        }
+        if parts.is_empty() { None } else { Some(parts.join(" ")) }
+    }
+}
+
+/// "h:mm:ss" with optional fractional seconds
+fn parse_clock(value: &str) -> Option<u64> {
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some((seconds * 1000.0).round() as u64)
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl PgnTags {
+    /// Writes the tag section (including the empty line after it) for a game starting at `start_board`.
+    pub fn write(&self, pgn: &mut String, result: &str, start_board: &Board) {
+        // Seven tag roster, in the order required by the standard
+        for (name, value) in [
+            ("Event", &self.event),
+            ("Site", &self.site),
+            ("Date", &self.date),
+            ("Round", &self.round),
+            ("White", &self.white),
+            ("Black", &self.black),
+        ] {
+            writeln!(pgn, "[{} \"{}\"]", name, escape_tag_value(value)).unwrap();
+        }
+        writeln!(pgn, "[Result \"{}\"]", result).unwrap();
+
+        let start_fen = start_board.to_fen();
+        if start_fen != Board::new_start_pos().to_fen() {
+            writeln!(pgn, "[SetUp \"1\"]").unwrap();
+            writeln!(pgn, "[FEN \"{}\"]", start_fen).unwrap();
+        }
+        if let Some(time_control) = &self.time_control {
+            writeln!(pgn, "[TimeControl \"{}\"]", escape_tag_value(time_control)).unwrap();
+        }
+        if let Some(termination) = &self.termination {
+            writeln!(pgn, "[Termination \"{}\"]", escape_tag_value(termination)).unwrap();
+        }
+        for (name, value) in &self.extra {
+            writeln!(pgn, "[{} \"{}\"]", name, escape_tag_value(value)).unwrap();
+        }
+
+        writeln!(pgn).unwrap();
+    }
+
+    /// Sets the field for a tag read from a PGN file. Tags without a field are kept in `extra`, except the ones
+    /// `write` derives from the game (Result, SetUp and FEN).
+    pub fn set(&mut self, name: &str, value: &str) {
+        let value = value.to_string();
+        match name {
+            "Event" => self.event = value,
+            "Site" => self.site = value,
+            "Date" => self.date = value,
+            "Round" => self.round = value,
+            "White" => self.white = value,
+            "Black" => self.black = value,
+            "TimeControl" => self.time_control = Some(value),
+            "Termination" => self.termination = Some(value),
+            "Result" | "SetUp" | "FEN" => {}
+            _ => match self.extra.iter_mut().find(|(n, _)| n == name) {
+                Some((_, v)) => *v = value,
+                None => self.extra.push((name.to_string(), value)),
+            },
+        }
+    }
+}
+
+/// Adds `{comment}` as one token per word so long comments can be wrapped too.
+pub(crate) fn push_comment_tokens(tokens: &mut Vec<String>, comment: &str) {
+    let words: Vec<&str> = comment.split_whitespace().collect();
+    for (j, word) in words.iter().enumerate() {
+        let mut token = word.to_string();
+        if j == 0 {
+            token.insert(0, '{');
+        }
+        if j == words.len() - 1 {
+            token.push('}');
+        }
+        tokens.push(token);
+    }
+}
+
+/// Joins movetext tokens with single spaces into lines of at most `PGN_LINE_WIDTH` characters.
+pub(crate) fn wrap_movetext(tokens: &[String]) -> String {
+    let mut text = String::new();
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > PGN_LINE_WIDTH {
+            text.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            text.push(' ');
+            line_len += 1;
+        }
+        text.push_str(token);
+        line_len += token.len();
+    }
+    text
+}
+
 impl Game {
+    /// Attaches a comment and/or eval and clock annotations to the last move played.
+    pub fn annotate_last_move(&mut self, annotation: MoveAnnotation) {
+        if let Some(last) = self.move_annotations.last_mut() {
+            *last = Some(annotation);
+        }
+    }
+
     pub fn to_pgn(&mut self) -> String {
         let mut pseudo_legal_moves = std::mem::take(&mut self.pseudo_legal_moves_container[0]);
         let mut legal_moves = std::mem::take(&mut self.legal_moves_container[0]);
 
         let mut pgn = String::new();
-
-        // Optional PGN tags
-        writeln!(pgn, "[Event \"?\"]").unwrap();
-        writeln!(pgn, "[Site \"?\"]").unwrap();
-        writeln!(pgn, "[Date \"????.??.??\"]").unwrap();
-        writeln!(pgn, "[Round \"?\"]").unwrap();
-        writeln!(pgn, "[White \"?\"]").unwrap();
-        writeln!(pgn, "[Black \"?\"]").unwrap();
-        writeln!(pgn, "[Result \"{}\"]", self.get_result_string()).unwrap();
-
-        writeln!(pgn).unwrap();
+        let result = self.get_result_string();
+        self.pgn_tags.write(&mut pgn, result, &self.start_board);
 
         let mut board_repetition_counts = HashMap::new();
+        let mut tokens = Vec::new();
+        let mut needs_number = true; // black moves get "N..." at the start and after comments
 
-        let mut board = Board::new_start_pos();
+        let mut board = self.start_board.clone();
         for (i, mv) in self.move_history.iter().enumerate() {
             *board_repetition_counts.entry(board.compute_zobrist_hash()).or_insert(0) += 1;
-            if i % 2 == 0 {
-                write!(pgn, "{}. ", board.fullmove_number).unwrap();
+            if board.turn == Color::White {
+                tokens.push(format!("{}.", board.fullmove_number));
+            } else if needs_number {
+                tokens.push(format!("{}...", board.fullmove_number));
             }
 
             let san = board.to_san(mv, &mut pseudo_legal_moves, &mut legal_moves, &mut board_repetition_counts);
-            write!(pgn, "{} ", san).unwrap();
+            tokens.push(san);
+            needs_number = false;
+
+            if let Some(comment) = self.move_annotations.get(i).and_then(|a| a.as_ref()).and_then(|a| a.to_comment()) {
+                push_comment_tokens(&mut tokens, &comment);
+                needs_number = true;
+            }
 
             board = board.make_move(mv);
         }
 
-        write!(pgn, "{}", self.get_result_string()).unwrap();
+        tokens.push(result.to_string());
+        pgn.push_str(&wrap_movetext(&tokens));
 
         self.pseudo_legal_moves_container[0] = pseudo_legal_moves;
         self.legal_moves_container[0] = legal_moves;
@@ -168,3 +548,61 @@ impl Game {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transposition_table_stays_within_its_size_limit() {
+        let mut game = Game::new(Board::new_start_pos(), 5, 3, Arc::new(AtomicBool::new(false)));
+        game.transposition_table.resize(1000);
+        game.find_best_move(5);
+        assert_eq!(game.transposition_table.slots.len(), 1000);
+        assert!(game.transposition_table.len() <= 1000);
+        assert!(game.nodes > 1000);
+
+        game.set_hash_size(1);
+        assert_eq!(game.transposition_table.len(), 0);
+        assert!(game.transposition_table.slots.is_empty());
+        assert_eq!(game.transposition_table.size(), tt_entries_for_mb(1));
+    }
+
+    #[test]
+    fn transposition_table_replaces_the_entry_in_a_slot() {
+        let entry = |score| TTEntry { score, depth: 3, node_type: NodeType::Exact, best_move: None };
+        let mut table = TranspositionTable::new(1);
+        assert!(table.get(42).is_none());
+        table.insert(42, entry(10));
+        assert_eq!(table.get(42).map(|e| e.score), Some(10));
+        table.insert(42, entry(20));
+        assert_eq!(table.get(42).map(|e| e.score), Some(20));
+        table.insert(7, entry(30)); // same slot, other position
+        assert!(table.get(42).is_none());
+        assert_eq!(table.get(7).map(|e| e.score), Some(30));
+        assert_eq!(table.len(), 1);
+
+        let mut table = TranspositionTable::new(1 << 10);
+        for key in 0..64u64 {
+            table.insert(key.wrapping_mul(0x9E37_79B9_7F4A_7C15), entry(key as i32));
+        }
+        assert!(table.len() > 50);
+        assert_eq!(table.get(5u64.wrapping_mul(0x9E37_79B9_7F4A_7C15)).map(|e| e.score), Some(5));
+    }
+
+    #[test]
+    fn search_tables_keep_the_game_s_hash_size() {
+        let mut game = Game::new(Board::new_start_pos(), 5, 3, Arc::new(AtomicBool::new(false)));
+        game.set_hash_size(2);
+        game.find_best_move(3);
+        let tables = game.take_search_tables();
+        assert_eq!(game.transposition_table.size(), tt_entries_for_mb(2));
+        assert!(tables.transposition_table.len() > 0);
+
+        game.restore_search_tables(tables.clone());
+        assert!(game.transposition_table.len() > 0);
+        game.set_hash_size(1);
+        game.restore_search_tables(tables);
+        assert_eq!(game.transposition_table.len(), 0);
+    }
+}
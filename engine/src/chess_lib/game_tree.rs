@@ -0,0 +1,384 @@
+// Game tree for analysis: the main line plus any number of nested variations
+//
+// Nodes live in an arena and refer to each other by index, so a `NodeId` handed out to the UI stays valid
+// until the tree is dropped. Deleted nodes are only unlinked from their parent, the arena never shrinks.
+// The first child of a node continues the line it is on, further children are variations.
+
+use crate::board::*;
+use crate::game::*;
+use crate::pgn::*;
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+pub type NodeId = usize;
+
+#[derive(Debug, Clone)]
+pub struct GameTreeNode {
+    pub mv: Option<ChessMove>, // None for the root
+    pub san: String,
+    pub board: Board, // position after `mv`
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    pub annotation: MoveAnnotation, // written after the move, for the root before the first move
+    pub comment_before: Option<String>, // written before the move, e.g. a comment opening a variation
+    pub nags: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GameTree {
+    nodes: Vec<GameTreeNode>,
+    current: NodeId,
+    pub pgn_tags: PgnTags,
+    pub result: String, // "1-0", "0-1", "1/2-1/2" or "*"
+}
+
+impl GameTree {
+    pub const ROOT: NodeId = 0;
+
+    pub fn new(board: Board) -> Self {
+        let root = GameTreeNode {
+            mv: None,
+            san: String::new(),
+            board,
+            parent: None,
+            children: Vec::new(),
+            annotation: MoveAnnotation::default(),
+            comment_before: None,
+            nags: Vec::new(),
+        };
+        GameTree { nodes: vec![root], current: Self::ROOT, pgn_tags: PgnTags::default(), result: "*".to_string() }
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&GameTreeNode> {
+        self.nodes.get(id)
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut GameTreeNode> {
+        self.nodes.get_mut(id)
+    }
+
+    pub fn current(&self) -> NodeId {
+        self.current
+    }
+
+    /// Position at the current node.
+    pub fn board(&self) -> &Board {
+        &self.nodes[self.current].board
+    }
+
+    pub fn start_board(&self) -> &Board {
+        &self.nodes[Self::ROOT].board
+    }
+
+    /// Whether `id` is still reachable from the root.
+    pub fn contains(&self, id: NodeId) -> bool {
+        let mut node = id;
+        while let Some(parent) = self.nodes.get(node).and_then(|n| n.parent) {
+            if !self.nodes[parent].children.contains(&node) {
+                return false;
+            }
+            node = parent;
+        }
+        node == Self::ROOT
+    }
+
+    /// Plays `mv` from the current node and moves there. An existing child with the same move is reused,
+    /// otherwise the move becomes a new variation (or the continuation if there is none yet).
+    /// Returns `None` if the move is illegal.
+    pub fn play(&mut self, mv: &ChessMove) -> Option<NodeId> {
+        let board = &self.nodes[self.current].board;
+        let mut pseudo_legal_moves = Vec::with_capacity(200);
+        let mut legal_moves = Vec::with_capacity(100);
+        board.generate_legal_moves(&mut pseudo_legal_moves, &mut legal_moves);
+        let mv = *legal_moves.iter().find(|m| m.from() == mv.from() && m.to() == mv.to() && m.promotion() == mv.promotion())?;
+
+        if let Some(&child) = self.nodes[self.current].children.iter().find(|&&c| self.nodes[c].mv == Some(mv)) {
+            self.current = child;
+            return Some(child);
+        }
+
+        let san = board.to_san(&mv, &mut pseudo_legal_moves, &mut legal_moves, &mut HashMap::new());
+        let node = GameTreeNode {
+            mv: Some(mv),
+            san,
+            board: board.make_move(&mv),
+            parent: Some(self.current),
+            children: Vec::new(),
+            annotation: MoveAnnotation::default(),
+            comment_before: None,
+            nags: Vec::new(),
+        };
+        let id = self.nodes.len();
+        self.nodes.push(node);
+        self.nodes[self.current].children.push(id);
+        self.current = id;
+        Some(id)
+    }
+
+    pub fn play_san(&mut self, san: &str) -> Result<NodeId, SanError> {
+        let mv = self.board().parse_san(san)?;
+        Ok(self.play(&mv).expect("parse_san only returns legal moves"))
+    }
+
+    /// Follows the continuation of the current line, false at the end of it.
+    pub fn forward(&mut self) -> bool {
+        match self.nodes[self.current].children.first() {
+            Some(&child) => {
+                self.current = child;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Goes to the parent node, false at the root.
+    pub fn back(&mut self) -> bool {
+        match self.nodes[self.current].parent {
+            Some(parent) => {
+                self.current = parent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn go_to(&mut self, id: NodeId) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        self.current = id;
+        true
+    }
+
+    pub fn go_to_start(&mut self) {
+        self.current = Self::ROOT;
+    }
+
+    /// Goes to the end of the current line.
+    pub fn go_to_end(&mut self) {
+        while self.forward() {}
+    }
+
+    /// Moves the variation starting at `id` one place up among its siblings (to the continuation if it is the first variation).
+    pub fn promote_variation(&mut self, id: NodeId) -> bool {
+        let Some(parent) = self.nodes.get(id).and_then(|n| n.parent) else {
+            return false;
+        };
+        let siblings = &mut self.nodes[parent].children;
+        match siblings.iter().position(|&c| c == id) {
+            Some(index) if index > 0 => {
+                siblings.swap(index - 1, index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Makes the line through `id` the main line of the game.
+    pub fn make_main_line(&mut self, id: NodeId) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        let mut node = id;
+        while let Some(parent) = self.nodes[node].parent {
+            let siblings = &mut self.nodes[parent].children;
+            let index = siblings.iter().position(|&c| c == node).unwrap();
+            let promoted = siblings.remove(index);
+            siblings.insert(0, promoted);
+            node = parent;
+        }
+        true
+    }
+
+    /// Removes `id` and everything after it. The current node moves to the parent if it was inside the removed part.
+    pub fn delete(&mut self, id: NodeId) -> bool {
+        if id == Self::ROOT || !self.contains(id) {
+            return false;
+        }
+        let in_deleted = self.path_to(self.current).contains(&id);
+        let parent = self.nodes[id].parent.unwrap();
+        self.nodes[parent].children.retain(|&c| c != id);
+        if in_deleted {
+            self.current = parent;
+        }
+        true
+    }
+
+    /// Nodes from the first move to `id`, in order.
+    pub fn path_to(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = Vec::new();
+        let mut node = id;
+        while let Some(parent) = self.nodes[node].parent {
+            path.push(node);
+            node = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Nodes of the main line, without the root.
+    pub fn main_line(&self) -> Vec<NodeId> {
+        let mut line = Vec::new();
+        let mut node = Self::ROOT;
+        while let Some(&child) = self.nodes[node].children.first() {
+            line.push(child);
+            node = child;
+        }
+        line
+    }
+
+    /// A `Game` that played the moves up to the current node, e.g. to let the engine analyse it.
+    pub fn to_game(&self, max_depth: u8, q_search_max_ply: u8, stop_signal: Arc<AtomicBool>) -> Game {
+        let mut game = Game::new(self.start_board().clone(), max_depth, q_search_max_ply, stop_signal);
+        game.pgn_tags = self.pgn_tags.clone();
+        for id in self.path_to(self.current) {
+            let node = &self.nodes[id];
+            game.make_move(&node.mv.unwrap());
+            if node.annotation != MoveAnnotation::default() {
+                game.annotate_last_move(node.annotation.clone());
+            }
+        }
+        game
+    }
+
+    /// Builds the tree of a parsed game, keeping its variations, comments and NAGs.
+    pub fn from_pgn(pgn: &PgnGame) -> Self {
+        let mut tree = GameTree::new(pgn.start.clone());
+        for (name, value) in &pgn.tags {
+            tree.pgn_tags.set(name, value);
+        }
+        tree.result = pgn.result.clone();
+        tree.add_pgn_line(Self::ROOT, &pgn.moves);
+        tree.current = Self::ROOT;
+        tree
+    }
+
+    fn add_pgn_line(&mut self, from: NodeId, moves: &[PgnMove]) {
+        let mut parent = from;
+        for m in moves {
+            // the comment before the first move of the game belongs to the root, any other one to its move
+            let game_comment = parent == Self::ROOT && self.nodes[Self::ROOT].children.is_empty();
+            self.current = parent;
+            let id = self.play(&m.mv).expect("PGN moves are checked while parsing");
+            if !m.comments_before.is_empty() {
+                let comment = m.comments_before.join(" ");
+                if game_comment {
+                    self.nodes[Self::ROOT].annotation = MoveAnnotation::from_comment(&comment);
+                } else {
+                    self.nodes[id].comment_before = Some(comment);
+                }
+            }
+            let node = &mut self.nodes[id];
+            node.nags.extend(&m.nags);
+            if !m.comments.is_empty() {
+                node.annotation = MoveAnnotation::from_comment(&m.comments.join(" "));
+            }
+
+            for variation in &m.variations {
+                self.add_pgn_line(parent, variation);
+            }
+            parent = id;
+        }
+    }
+
+    /// PGN of the whole tree with variations, comments and NAGs.
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        self.pgn_tags.write(&mut pgn, &self.result, self.start_board());
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = self.nodes[Self::ROOT].annotation.to_comment() {
+            push_comment_tokens(&mut tokens, &comment);
+        }
+        self.write_line(Self::ROOT, true, &mut tokens);
+        tokens.push(self.result.clone());
+
+        pgn.push_str(&wrap_movetext(&tokens));
+        pgn
+    }
+
+    /// Writes the line continuing after `from` with the variations branching off it.
+    fn write_line(&self, from: NodeId, mut needs_number: bool, tokens: &mut Vec<String>) {
+        let mut node = from;
+        while let Some(&main) = self.nodes[node].children.first() {
+            needs_number = self.write_move(main, needs_number, tokens);
+
+            for &variation in &self.nodes[node].children[1..] {
+                let open = tokens.len();
+                let after_comment = self.write_move(variation, true, tokens);
+                tokens[open].insert(0, '(');
+                self.write_line(variation, after_comment, tokens);
+                tokens.last_mut().unwrap().push(')');
+                needs_number = true;
+            }
+            node = main;
+        }
+    }
+
+    /// Writes the comment before, number (if needed), SAN, NAGs and comment of one move. Returns whether the next move needs its number.
+    fn write_move(&self, id: NodeId, needs_number: bool, tokens: &mut Vec<String>) -> bool {
+        let node = &self.nodes[id];
+        let parent = &self.nodes[node.parent.unwrap()].board;
+        let mut needs_number = needs_number;
+        if let Some(comment) = &node.comment_before {
+            push_comment_tokens(tokens, comment);
+            needs_number = true;
+        }
+        if parent.turn == Color::White {
+            tokens.push(format!("{}.", parent.fullmove_number));
+        } else if needs_number {
+            tokens.push(format!("{}...", parent.fullmove_number));
+        }
+        tokens.push(node.san.clone());
+        tokens.extend(node.nags.iter().map(|nag| format!("${}", nag)));
+
+        match node.annotation.to_comment() {
+            Some(comment) => {
+                push_comment_tokens(tokens, &comment);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) -> (GameTree, String) {
+        let tree = GameTree::from_pgn(&PgnGame::parse(text).unwrap());
+        let pgn = tree.to_pgn();
+        (tree, pgn)
+    }
+
+    #[test]
+    fn keeps_tags_without_a_field() {
+        let text = "[Event \"Test\"]\n[WhiteElo \"2100\"]\n[ECO \"B20\"]\n[Annotator \"Me \\\"too\\\"\"]\n[Result \"1-0\"]\n\n1. e4 c5 1-0";
+        let (tree, pgn) = round_trip(text);
+        assert_eq!(tree.pgn_tags.extra, [
+            ("WhiteElo".to_string(), "2100".to_string()),
+            ("ECO".to_string(), "B20".to_string()),
+            ("Annotator".to_string(), "Me \"too\"".to_string()),
+        ]);
+        assert!(pgn.contains("[WhiteElo \"2100\"]\n[ECO \"B20\"]\n[Annotator \"Me \\\"too\\\"\"]\n"));
+        assert_eq!(pgn.matches("[Result ").count(), 1);
+
+        let again = PgnGame::parse(&pgn).unwrap();
+        assert!(again.tags.contains(&("Annotator".to_string(), "Me \"too\"".to_string())));
+    }
+
+    #[test]
+    fn keeps_comments_before_moves() {
+        let (tree, pgn) = round_trip("{opening} 1. e4 ({idea} 1. d4 d5) 1... e5 ({why not} 1... c5) *");
+        assert_eq!(tree.nodes[GameTree::ROOT].annotation.to_comment().as_deref(), Some("opening"));
+        let d4 = tree.nodes[GameTree::ROOT].children[1];
+        assert_eq!(tree.nodes[d4].comment_before.as_deref(), Some("idea"));
+        assert_eq!(pgn.lines().last().unwrap(), "{opening} 1. e4 ({idea} 1. d4 d5) 1... e5 ({why not} 1... c5) *");
+
+        let (_, again) = round_trip(&pgn);
+        assert_eq!(again, pgn);
+    }
+}
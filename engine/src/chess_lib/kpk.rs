@@ -0,0 +1,233 @@
+// King and pawn vs king bitbase, generated by retrograde analysis on first use
+// see: https://www.chessprogramming.org/KPK
+//
+// Positions are normalised so that White has the pawn and the pawn is on files a-d.
+// Index layout: white king (6 bits) | black king (6 bits) | side to move (1 bit) | pawn file (2 bits) | 6 - pawn rank (3 bits)
+
+use crate::board::*;
+
+use once_cell::sync::Lazy;
+
+const KPK_MAX_INDEX: usize = 2 * 24 * 64 * 64; // side to move * pawn squares (files a-d, ranks 2-7) * white king * black king
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KpkResult {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+#[inline]
+fn kpk_index(white_to_move: bool, black_king: Square, white_king: Square, pawn: Square) -> usize {
+    white_king.to_u8() as usize
+        | (black_king.to_u8() as usize) << 6
+        | (!white_to_move as usize) << 12
+        | (pawn.file() as usize) << 13
+        | (6 - pawn.rank() as usize) << 15
+}
+
+#[derive(Debug, Clone, Copy)]
+struct KpkPosition {
+    white_to_move: bool,
+    white_king: Square,
+    black_king: Square,
+    pawn: Square,
+    result: KpkResult,
+}
+
+impl KpkPosition {
+    fn new(index: usize) -> Self {
+        let white_king = Square::from_u8((index & 0x3F) as u8);
+        let black_king = Square::from_u8(((index >> 6) & 0x3F) as u8);
+        let white_to_move = (index >> 12) & 1 == 0;
+        let pawn = Square::from_file_rank(((index >> 13) & 0x3) as u8, (6 - ((index >> 15) & 0x7)) as u8);
+
+        let pawn_attacks = PRECOMPUTED.pawn_attacks[Color::White as usize][pawn.to_u8() as usize];
+        let black_king_moves = PRECOMPUTED.king_attacks[black_king.to_u8() as usize];
+        let white_king_moves = PRECOMPUTED.king_attacks[white_king.to_u8() as usize];
+        let promotion_sq = Square::from_file_rank(pawn.file(), 7);
+
+        let result = if white_king.distance(black_king) <= 1
+            || white_king == pawn
+            || black_king == pawn
+            || (white_to_move && pawn_attacks.is_set(black_king))
+        {
+            KpkResult::Invalid
+        } else if white_to_move
+            && pawn.rank() == 6
+            && white_king != promotion_sq
+            && (black_king.distance(promotion_sq) > 1 || white_king.distance(promotion_sq) == 1)
+        {
+            KpkResult::Win // the pawn promotes without being captured
+        } else if !white_to_move
+            && ((black_king_moves & !(white_king_moves | pawn_attacks)).is_empty() // stalemate
+                || (black_king_moves & !white_king_moves).is_set(pawn)) // undefended pawn is taken
+        {
+            KpkResult::Draw
+        } else {
+            KpkResult::Unknown
+        };
+
+        KpkPosition { white_to_move, white_king, black_king, pawn, result }
+    }
+
+    /// White wins if any move wins, Black draws if any move draws.
+    /// Moves into illegal positions lead to `Invalid` children and are ignored.
+    fn classify(&self, db: &[KpkPosition]) -> KpkResult {
+        let (mut any_win, mut any_draw, mut any_unknown) = (false, false, false);
+        let mut visit = |result: KpkResult| match result {
+            KpkResult::Win => any_win = true,
+            KpkResult::Draw => any_draw = true,
+            KpkResult::Unknown => any_unknown = true,
+            KpkResult::Invalid => {}
+        };
+
+        if self.white_to_move {
+            for to in PRECOMPUTED.king_attacks[self.white_king.to_u8() as usize].iter() {
+                visit(db[kpk_index(false, self.black_king, to, self.pawn)].result);
+            }
+            if self.pawn.rank() < 6 {
+                // Promotions are resolved by the initial classification
+                let push = self.pawn.try_offset(0, 1).unwrap();
+                if push != self.white_king && push != self.black_king {
+                    visit(db[kpk_index(false, self.black_king, self.white_king, push)].result);
+                    if self.pawn.rank() == 1 {
+                        let double_push = push.try_offset(0, 1).unwrap();
+                        if double_push != self.white_king && double_push != self.black_king {
+                            visit(db[kpk_index(false, self.black_king, self.white_king, double_push)].result);
+                        }
+                    }
+                }
+            }
+            if any_win { KpkResult::Win } else if any_unknown { KpkResult::Unknown } else { KpkResult::Draw }
+        } else {
+            for to in PRECOMPUTED.king_attacks[self.black_king.to_u8() as usize].iter() {
+                visit(db[kpk_index(true, to, self.white_king, self.pawn)].result);
+            }
+            if any_draw { KpkResult::Draw } else if any_unknown { KpkResult::Unknown } else { KpkResult::Win }
+        }
+    }
+}
+
+/// One bit per position, set when White wins.
+struct KpkBitbase {
+    wins: Vec<u32>,
+}
+
+impl KpkBitbase {
+    fn generate() -> Self {
+        let mut db: Vec<KpkPosition> = (0..KPK_MAX_INDEX).map(KpkPosition::new).collect();
+
+        // Propagate until nothing changes, whatever is still unknown afterwards is a draw
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in 0..KPK_MAX_INDEX {
+                if db[idx].result == KpkResult::Unknown {
+                    let result = db[idx].classify(&db);
+                    if result != KpkResult::Unknown {
+                        db[idx].result = result;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut wins = vec![0u32; KPK_MAX_INDEX / 32];
+        for (idx, position) in db.iter().enumerate() {
+            if position.result == KpkResult::Win {
+                wins[idx / 32] |= 1 << (idx % 32);
+            }
+        }
+        KpkBitbase { wins }
+    }
+
+    fn probe(&self, white_to_move: bool, white_king: Square, pawn: Square, black_king: Square) -> bool {
+        let idx = kpk_index(white_to_move, black_king, white_king, pawn);
+        self.wins[idx / 32] & (1 << (idx % 32)) != 0
+    }
+}
+
+static KPK_BITBASE: Lazy<KpkBitbase> = Lazy::new(KpkBitbase::generate);
+
+/// Forces the bitbase to be generated now rather than during the first search that needs it.
+pub fn init_kpk_bitbase() {
+    Lazy::force(&KPK_BITBASE);
+}
+
+impl Board {
+    /// Exact result of a king and pawn vs king position: `Some(true)` if the side with the pawn wins,
+    /// `Some(false)` if it is a draw, `None` if the material is not KPK.
+    pub fn probe_kpk(&self) -> Option<bool> {
+        if self.occupied_bb.popcount() != 3 {
+            return None;
+        }
+        let pawns = self.piece_bbs[PieceType::Pawn as usize];
+        let pawn = pawns.lsb()?;
+        if pawns.popcount() != 1 {
+            return None;
+        }
+
+        let strong = if self.color_bbs[Color::White as usize].is_set(pawn) { Color::White } else { Color::Black };
+        let strong_king = self.find_king_square(strong)?;
+        let weak_king = self.find_king_square(strong.opponent())?;
+
+        // Normalise to White having the pawn on files a-d
+        let normalise = |sq: Square| {
+            let sq = if strong == Color::Black { sq.to_u8() ^ 56 } else { sq.to_u8() };
+            let sq = if pawn.file() >= 4 { sq ^ 7 } else { sq };
+            Square::from_u8(sq)
+        };
+
+        Some(KPK_BITBASE.probe(self.turn == strong, normalise(strong_king), normalise(pawn), normalise(weak_king)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(fen: &str) -> Option<bool> {
+        Board::from_fen(&fen.parse::<Fen>().unwrap()).unwrap().probe_kpk()
+    }
+
+    #[test]
+    fn rook_pawns_are_drawn_with_the_king_in_front() {
+        assert_eq!(probe("k7/8/1K6/P7/8/8/8/8 w - - 0 1"), Some(false));
+        assert_eq!(probe("k7/8/1K6/P7/8/8/8/8 b - - 0 1"), Some(false));
+        assert_eq!(probe("7k/8/6K1/7P/8/8/8/8 w - - 0 1"), Some(false));
+        // A knight pawn wins from the same setup
+        assert_eq!(probe("1k6/8/2K5/1P6/8/8/8/8 w - - 0 1"), Some(true));
+    }
+
+    #[test]
+    fn the_side_to_move_decides_opposition_positions() {
+        // Kings in opposition in front of the pawn: whoever has to move gives way
+        assert_eq!(probe("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), Some(false));
+        assert_eq!(probe("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1"), Some(true));
+        assert_eq!(probe("8/2k5/8/2K5/2P5/8/8/8 w - - 0 1"), Some(false));
+        assert_eq!(probe("8/2k5/8/2K5/2P5/8/8/8 b - - 0 1"), Some(true));
+        // With the king on the sixth rank in front of the pawn White wins whoever moves
+        assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(true));
+        assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Some(true));
+    }
+
+    #[test]
+    fn black_pawns_are_mirrored_white_ones() {
+        assert_eq!(probe("8/8/8/4p3/4k3/8/4K3/8 b - - 0 1"), Some(false));
+        assert_eq!(probe("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1"), Some(true));
+        assert_eq!(probe("8/8/8/8/p7/1k6/8/K7 w - - 0 1"), Some(false));
+        // A pawn that simply runs, and one the king takes
+        assert_eq!(probe("7K/8/8/8/8/8/1p6/7k w - - 0 1"), Some(true));
+        assert_eq!(probe("8/8/8/8/8/4p3/3K4/k7 w - - 0 1"), Some(false));
+    }
+
+    #[test]
+    fn other_material_is_not_kpk() {
+        assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/7R w - - 0 1"), None);
+        assert_eq!(probe("4k3/8/4K3/3PP3/8/8/8/8 w - - 0 1"), None);
+        assert_eq!(probe("4k3/8/4K3/8/8/8/8/8 w - - 0 1"), None);
+    }
+}
@@ -162,7 +162,7 @@ impl Board {
     }
 
     // Helper: Get rook attacks (horizontal/vertical rays)
-    fn get_rook_attacks(&self, sq: Square, occupied: Bitboard) -> Bitboard {
+    pub fn get_rook_attacks(&self, sq: Square, occupied: Bitboard) -> Bitboard {
         let mut attacks = Bitboard::EMPTY;
         let directions = [(0, 1), (0, -1), (1, 0), (-1, 0)]; // N, S, E, W (rank_offset, file_offset)
         for (dr, df) in directions.iter() {
@@ -179,7 +179,7 @@ impl Board {
     }
 
     // Helper: Get bishop attacks (diagonal rays)
-    fn get_bishop_attacks(&self, sq: Square, occupied: Bitboard) -> Bitboard {
+    pub fn get_bishop_attacks(&self, sq: Square, occupied: Bitboard) -> Bitboard {
         let mut attacks = Bitboard::EMPTY;
         let directions = [(1, 1), (1, -1), (-1, 1), (-1, -1)]; // NE, NW, SE, SW
         for (dr, df) in directions.iter() {
@@ -230,6 +230,22 @@ impl Board {
     }
 
 
+    /// Opponent pieces giving check to the side to move.
+    pub fn checkers(&self) -> Bitboard {
+        let Some(king_sq) = self.find_king_square(self.turn) else {
+            return Bitboard::EMPTY;
+        };
+        let k = king_sq.to_u8() as usize;
+        let pieces = |piece_type: PieceType| self.piece_bbs[piece_type as usize];
+
+        let attackers = (PRECOMPUTED.pawn_attacks[self.turn as usize][k] & pieces(PieceType::Pawn))
+            | (PRECOMPUTED.knight_attacks[k] & pieces(PieceType::Knight))
+            | (self.get_rook_attacks(king_sq, self.occupied_bb) & (pieces(PieceType::Rook) | pieces(PieceType::Queen)))
+            | (self.get_bishop_attacks(king_sq, self.occupied_bb) & (pieces(PieceType::Bishop) | pieces(PieceType::Queen)));
+        attackers & self.color_bbs[self.turn.opponent() as usize]
+    }
+
+
     pub fn get_attacked_squares(&self, attacker_color: Color) -> Bitboard {
         let mut attacked_squares = Bitboard::new();
         let opponent_pieces = self.color_bbs[attacker_color as usize];
@@ -0,0 +1,495 @@
+// Simple 768 -> N -> 1 network (perspective network, one accumulator per side)
+// see: https://www.chessprogramming.org/NNUE
+//
+// File format (all little-endian):
+//   magic "CFNN", u32 version, u32 hidden size N (multiple of 8)
+//   i16 feature weights [768][N], i16 feature biases [N]
+//   i16 output weights [2N] (side to move half first), i32 output bias
+
+use crate::board::*;
+use crate::game::*;
+use crate::terminal_states::*;
+use crate::endgame::*;
+use crate::byte_reader::*;
+
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+use std::fmt;
+
+pub const NNUE_INPUTS: usize = 768;
+const NNUE_MAGIC: &[u8; 4] = b"CFNN";
+const NNUE_VERSION: u32 = 1;
+
+const QA: i32 = 255; // accumulator quantisation, also the clipped ReLU ceiling
+const QB: i32 = 64;  // output weight quantisation
+const SCALE: i32 = 400; // network output -> centipawns
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NnueError {
+    Io(String),
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    InvalidHiddenSize(u32),
+    UnexpectedEof,
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for NnueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NnueError::Io(e) => write!(f, "Could not read network file: {}", e),
+            NnueError::InvalidMagic => write!(f, "Not a ChessFlow network file"),
+            NnueError::UnsupportedVersion(v) => write!(f, "Unsupported network version {}", v),
+            NnueError::InvalidHiddenSize(n) => write!(f, "Invalid hidden layer size {} (expected a non-zero multiple of 8)", n),
+            NnueError::UnexpectedEof => write!(f, "Network file is truncated"),
+            NnueError::TrailingBytes(n) => write!(f, "Network file has {} unexpected trailing bytes", n),
+        }
+    }
+}
+impl std::error::Error for NnueError {}
+
+impl From<UnexpectedEof> for NnueError {
+    fn from(_: UnexpectedEof) -> Self {
+        NnueError::UnexpectedEof
+    }
+}
+
+
+pub struct Network {
+    pub hidden: usize,
+    pub feature_weights: Vec<i16>, // [NNUE_INPUTS][hidden]
+    pub feature_bias: Vec<i16>,    // [hidden]
+    pub output_weights: Vec<i16>,  // [2 * hidden]
+    pub output_bias: i32,
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Network(768->{}->1)", self.hidden)
+    }
+}
+
+impl Network {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NnueError> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.take(4)? != NNUE_MAGIC {
+            return Err(NnueError::InvalidMagic);
+        }
+        let version = reader.u32()?;
+        if version != NNUE_VERSION {
+            return Err(NnueError::UnsupportedVersion(version));
+        }
+        let hidden = reader.u32()?;
+        if hidden == 0 || !hidden.is_multiple_of(8) || hidden > 4096 {
+            return Err(NnueError::InvalidHiddenSize(hidden));
+        }
+        let hidden = hidden as usize;
+
+        let network = Network {
+            hidden,
+            feature_weights: reader.i16_vec(NNUE_INPUTS * hidden)?,
+            feature_bias: reader.i16_vec(hidden)?,
+            output_weights: reader.i16_vec(2 * hidden)?,
+            output_bias: reader.i32()?,
+        };
+
+        if reader.remaining() != 0 {
+            return Err(NnueError::TrailingBytes(reader.remaining()));
+        }
+        Ok(network)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_file(path: &str) -> Result<Self, NnueError> {
+        let bytes = std::fs::read(path).map_err(|e| NnueError::Io(e.to_string()))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Index of a piece in the input layer as seen from `perspective`.
+    /// Black's view is mirrored vertically so both halves share the same weights.
+    #[inline]
+    fn feature_index(perspective: Color, piece: PieceType, color: Color, sq: Square) -> usize {
+        let (side, sq) = match perspective {
+            Color::White => ((color != Color::White) as usize, sq.to_u8() as usize),
+            Color::Black => ((color != Color::Black) as usize, (sq.to_u8() ^ 56) as usize),
+        };
+        side * 384 + piece as usize * 64 + sq
+    }
+
+    #[inline]
+    fn feature_row(&self, index: usize) -> &[i16] {
+        &self.feature_weights[index * self.hidden..(index + 1) * self.hidden]
+    }
+
+    /// Rebuilds both halves of the accumulator from scratch.
+    pub fn refresh(&self, acc: &mut Accumulator, board: &Board) {
+        for perspective in [Color::White, Color::Black] {
+            let values = &mut acc.values[perspective as usize];
+            values.clear();
+            values.extend_from_slice(&self.feature_bias);
+
+            for pt in PieceType::ALL {
+                for color in [Color::White, Color::Black] {
+                    let bb = board.piece_bbs[pt as usize] & board.color_bbs[color as usize];
+                    for sq in bb.iter() {
+                        simd::add_row(values, self.feature_row(Self::feature_index(perspective, pt, color, sq)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Computes the accumulator after `mv` from the one before it (the incremental "make").
+    /// `board` is the position *before* the move; undoing is simply dropping `child`.
+    pub fn apply_move(&self, parent: &Accumulator, child: &mut Accumulator, board: &Board, mv: &ChessMove) {
+        let color = board.turn;
+        let (moved, _) = board.piece_on_square(mv.from()).expect("There should be a piece on the 'from' square");
+
+        // At most two pieces appear and two disappear (castling, captures with promotion)
+        let mut added: [Option<(PieceType, Color, Square)>; 2] = [None; 2];
+        let mut removed: [Option<(PieceType, Color, Square)>; 2] = [None; 2];
+
+        removed[0] = Some((moved, color, mv.from()));
+        added[0] = Some((mv.promotion().unwrap_or(moved), color, mv.to()));
+
+        if let Some((captured, captured_color)) = board.piece_on_square(mv.to()) {
+            removed[1] = Some((captured, captured_color, mv.to()));
+        } else if moved == PieceType::Pawn && Some(mv.to()) == board.en_passant_square {
+            let captured_sq = Square::from_file_rank(mv.to().file(), mv.from().rank());
+            removed[1] = Some((PieceType::Pawn, color.opponent(), captured_sq));
+        }
+
+        if moved == PieceType::King && (mv.to().file() as i8 - mv.from().file() as i8).abs() == 2 {
+            let rank = mv.from().rank();
+            let (rook_from, rook_to) = if mv.to().file() == 6 { (7, 5) } else { (0, 3) };
+            removed[1] = Some((PieceType::Rook, color, Square::from_file_rank(rook_from, rank)));
+            added[1] = Some((PieceType::Rook, color, Square::from_file_rank(rook_to, rank)));
+        }
+
+        for perspective in [Color::White, Color::Black] {
+            let values = &mut child.values[perspective as usize];
+            values.clear();
+            values.extend_from_slice(&parent.values[perspective as usize]);
+
+            for &(pt, c, sq) in removed.iter().flatten() {
+                simd::sub_row(values, self.feature_row(Self::feature_index(perspective, pt, c, sq)));
+            }
+            for &(pt, c, sq) in added.iter().flatten() {
+                simd::add_row(values, self.feature_row(Self::feature_index(perspective, pt, c, sq)));
+            }
+        }
+    }
+
+    /// Network output in centipawns from the side to move's point of view.
+    pub fn evaluate(&self, acc: &Accumulator, side_to_move: Color) -> i32 {
+        let us = &acc.values[side_to_move as usize];
+        let them = &acc.values[side_to_move.opponent() as usize];
+
+        let sum = simd::crelu_dot(us, &self.output_weights[..self.hidden])
+                + simd::crelu_dot(them, &self.output_weights[self.hidden..]);
+
+        (sum + self.output_bias) * SCALE / (QA * QB)
+    }
+}
+
+
+/// Hidden layer pre-activations for both perspectives, indexed by `Color`.
+#[derive(Debug, Clone, Default)]
+pub struct Accumulator {
+    pub values: [Vec<i16>; 2],
+}
+
+
+// Loaded network shared by every search thread, `None` until a network file is loaded
+static NNUE_NETWORK: Lazy<RwLock<Option<Arc<Network>>>> = Lazy::new(|| RwLock::new(None));
+
+pub fn nnue_network() -> Option<Arc<Network>> {
+    NNUE_NETWORK.read().unwrap().clone()
+}
+
+pub fn set_nnue_network(network: Option<Network>) {
+    *NNUE_NETWORK.write().unwrap() = network.map(Arc::new);
+}
+
+
+impl Game {
+    /// Picks up the loaded network (when `use_nnue` is set) and rebuilds the root accumulator.
+    pub fn nnue_begin_search(&mut self) {
+        self.nnue_network = if self.use_nnue { nnue_network() } else { None };
+        self.nnue_ply = 0;
+        if let Some(network) = &self.nnue_network {
+            if self.nnue_stack.is_empty() {
+                self.nnue_stack.push(Accumulator::default());
+            }
+            network.refresh(&mut self.nnue_stack[0], &self.board);
+        }
+    }
+
+    /// Pushes the accumulator for the position after `mv`, `board` being the position before it.
+    #[inline]
+    pub fn nnue_push(&mut self, board: &Board, mv: &ChessMove) {
+        if let Some(network) = &self.nnue_network {
+            let ply = self.nnue_ply;
+            if self.nnue_stack.len() <= ply + 1 {
+                self.nnue_stack.push(Accumulator::default());
+            }
+            let (parents, children) = self.nnue_stack.split_at_mut(ply + 1);
+            network.apply_move(&parents[ply], &mut children[0], board, mv);
+            self.nnue_ply += 1;
+        }
+    }
+
+    #[inline]
+    pub fn nnue_pop(&mut self) {
+        if self.nnue_network.is_some() {
+            self.nnue_ply -= 1;
+        }
+    }
+
+    /// Static evaluation of a quiet position from White's perspective.
+    /// Uses the network when one is active, the handcrafted `Board::evaluate` otherwise.
+    /// Known endgames are always left to the specialised evaluators.
+    pub fn static_eval(&self, board: &Board, pseudo_legal_moves: &mut Vec<ChessMove>, legal_moves: &mut Vec<ChessMove>) -> i32 {
+        if let Some(network) = &self.nnue_network {
+            if let Some(score) = board.evaluate_endgame(&MaterialSignature::of(board), &self.eval_params) {
+                return score;
+            }
+
+            let score = network.evaluate(&self.nnue_stack[self.nnue_ply], board.turn);
+            return if board.turn == Color::White { score } else { -score };
+        }
+        board.evaluate(0, pseudo_legal_moves, legal_moves, GameState::Ongoing, &self.eval_params)
+    }
+}
+
+
+// Vector kernels. Every path must produce exactly the same result as the scalar one.
+// Row lengths are always a multiple of 8 (checked when loading the network).
+mod simd {
+    #[cfg(not(any(target_arch = "x86_64", all(target_arch = "wasm32", target_feature = "simd128"))))]
+    pub fn add_row(acc: &mut [i16], row: &[i16]) {
+        scalar::add_row(acc, row)
+    }
+    #[cfg(not(any(target_arch = "x86_64", all(target_arch = "wasm32", target_feature = "simd128"))))]
+    pub fn sub_row(acc: &mut [i16], row: &[i16]) {
+        scalar::sub_row(acc, row)
+    }
+    #[cfg(not(any(target_arch = "x86_64", all(target_arch = "wasm32", target_feature = "simd128"))))]
+    pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        scalar::crelu_dot(values, weights)
+    }
+
+    #[allow(dead_code)] // only the fallback when no vector path is compiled in
+    pub(super) mod scalar {
+        use super::super::QA;
+
+        pub fn add_row(acc: &mut [i16], row: &[i16]) {
+            for (a, r) in acc.iter_mut().zip(row) {
+                *a = a.wrapping_add(*r);
+            }
+        }
+
+        pub fn sub_row(acc: &mut [i16], row: &[i16]) {
+            for (a, r) in acc.iter_mut().zip(row) {
+                *a = a.wrapping_sub(*r);
+            }
+        }
+
+        pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+            values.iter().zip(weights)
+                .map(|(&v, &w)| (v as i32).clamp(0, QA) * w as i32)
+                .sum()
+        }
+    }
+
+    // SSE2 is part of the x86_64 baseline, so no runtime detection is needed
+    #[cfg(target_arch = "x86_64")]
+    pub fn add_row(acc: &mut [i16], row: &[i16]) {
+        use std::arch::x86_64::*;
+        debug_assert!(acc.len() == row.len() && acc.len().is_multiple_of(8));
+        for i in (0..acc.len()).step_by(8) {
+            unsafe {
+                let a = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+                let r = _mm_loadu_si128(row.as_ptr().add(i) as *const __m128i);
+                _mm_storeu_si128(acc.as_mut_ptr().add(i) as *mut __m128i, _mm_add_epi16(a, r));
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn sub_row(acc: &mut [i16], row: &[i16]) {
+        use std::arch::x86_64::*;
+        debug_assert!(acc.len() == row.len() && acc.len().is_multiple_of(8));
+        for i in (0..acc.len()).step_by(8) {
+            unsafe {
+                let a = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+                let r = _mm_loadu_si128(row.as_ptr().add(i) as *const __m128i);
+                _mm_storeu_si128(acc.as_mut_ptr().add(i) as *mut __m128i, _mm_sub_epi16(a, r));
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        use std::arch::x86_64::*;
+        debug_assert!(values.len() == weights.len() && values.len().is_multiple_of(8));
+        unsafe {
+            let zero = _mm_setzero_si128();
+            let ceiling = _mm_set1_epi16(super::QA as i16);
+            let mut sum = _mm_setzero_si128();
+            for i in (0..values.len()).step_by(8) {
+                let v = _mm_loadu_si128(values.as_ptr().add(i) as *const __m128i);
+                let w = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+                let clipped = _mm_min_epi16(_mm_max_epi16(v, zero), ceiling);
+                sum = _mm_add_epi32(sum, _mm_madd_epi16(clipped, w));
+            }
+            let mut lanes = [0i32; 4];
+            _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, sum);
+            lanes.iter().sum()
+        }
+    }
+
+    // Only used when the wasm build enables the feature (RUSTFLAGS="-C target-feature=+simd128")
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    pub fn add_row(acc: &mut [i16], row: &[i16]) {
+        use std::arch::wasm32::*;
+        debug_assert!(acc.len() == row.len() && acc.len().is_multiple_of(8));
+        for i in (0..acc.len()).step_by(8) {
+            unsafe {
+                let a = v128_load(acc.as_ptr().add(i) as *const v128);
+                let r = v128_load(row.as_ptr().add(i) as *const v128);
+                v128_store(acc.as_mut_ptr().add(i) as *mut v128, i16x8_add(a, r));
+            }
+        }
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    pub fn sub_row(acc: &mut [i16], row: &[i16]) {
+        use std::arch::wasm32::*;
+        debug_assert!(acc.len() == row.len() && acc.len().is_multiple_of(8));
+        for i in (0..acc.len()).step_by(8) {
+            unsafe {
+                let a = v128_load(acc.as_ptr().add(i) as *const v128);
+                let r = v128_load(row.as_ptr().add(i) as *const v128);
+                v128_store(acc.as_mut_ptr().add(i) as *mut v128, i16x8_sub(a, r));
+            }
+        }
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        use std::arch::wasm32::*;
+        debug_assert!(values.len() == weights.len() && values.len().is_multiple_of(8));
+        let zero = i16x8_splat(0);
+        let ceiling = i16x8_splat(super::QA as i16);
+        let mut sum = i32x4_splat(0);
+        for i in (0..values.len()).step_by(8) {
+            unsafe {
+                let v = v128_load(values.as_ptr().add(i) as *const v128);
+                let w = v128_load(weights.as_ptr().add(i) as *const v128);
+                let clipped = i16x8_min(i16x8_max(v, zero), ceiling);
+                sum = i32x4_add(sum, i32x4_dot_i16x8(clipped, w));
+            }
+        }
+        i32x4_extract_lane::<0>(sum) + i32x4_extract_lane::<1>(sum)
+            + i32x4_extract_lane::<2>(sum) + i32x4_extract_lane::<3>(sum)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const HIDDEN: usize = 32;
+
+    fn random_network(seed: u64) -> Network {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut weights = |len: usize| (0..len).map(|_| rng.random_range(-300..=300)).collect::<Vec<i16>>();
+        Network {
+            hidden: HIDDEN,
+            feature_weights: weights(NNUE_INPUTS * HIDDEN),
+            feature_bias: weights(HIDDEN),
+            output_weights: weights(2 * HIDDEN),
+            output_bias: 1234,
+        }
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(&fen.parse::<Fen>().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn incremental_accumulator_matches_a_refresh_after_every_kind_of_move() {
+        let network = random_network(1);
+        let (mut captures, mut en_passants, mut king_castles, mut queen_castles, mut promotions, mut capture_promotions) = (0, 0, 0, 0, 0, 0);
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 10",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/5N2/PPP1PPPP/RNBQKB1R b KQkq d3 0 3",
+            "3r1n2/4P3/8/8/8/8/k7/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/2p5/1N2K3 b - - 0 1",
+        ] {
+            let board = board(fen);
+            let mut parent = Accumulator::default();
+            network.refresh(&mut parent, &board);
+
+            let (mut pseudo_legal_moves, mut legal_moves) = (Vec::new(), Vec::new());
+            board.generate_legal_moves(&mut pseudo_legal_moves, &mut legal_moves);
+            for mv in &legal_moves {
+                let (moved, _) = board.piece_on_square(mv.from()).unwrap();
+                let captured = board.piece_on_square(mv.to()).is_some();
+                match (moved, mv.promotion(), captured) {
+                    (PieceType::Pawn, Some(_), true) => capture_promotions += 1,
+                    (PieceType::Pawn, Some(_), false) => promotions += 1,
+                    (PieceType::Pawn, None, false) if Some(mv.to()) == board.en_passant_square => en_passants += 1,
+                    (PieceType::King, _, _) if mv.to().file() as i8 - mv.from().file() as i8 == 2 => king_castles += 1,
+                    (PieceType::King, _, _) if mv.to().file() as i8 - mv.from().file() as i8 == -2 => queen_castles += 1,
+                    (_, _, true) => captures += 1,
+                    _ => {}
+                }
+
+                let mut incremental = Accumulator::default();
+                network.apply_move(&parent, &mut incremental, &board, mv);
+                let after = board.make_move(mv);
+                let mut refreshed = Accumulator::default();
+                network.refresh(&mut refreshed, &after);
+                assert_eq!(incremental.values, refreshed.values, "{} {}", fen, mv.to_uci());
+                assert_eq!(network.evaluate(&incremental, after.turn), network.evaluate(&refreshed, after.turn));
+            }
+        }
+
+        assert!(captures > 0 && en_passants >= 2 && king_castles >= 2 && queen_castles >= 2);
+        assert!(promotions >= 8 && capture_promotions >= 12);
+    }
+
+    #[test]
+    fn vector_kernels_match_the_scalar_ones() {
+        let network = random_network(2);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..100 {
+            let row = network.feature_row(rng.random_range(0..NNUE_INPUTS));
+            // Values beyond the clipping range on both sides and wrapping additions
+            let values: Vec<i16> = (0..HIDDEN).map(|_| rng.random_range(i16::MIN..=i16::MAX)).collect();
+
+            let (mut vector, mut scalar) = (values.clone(), values.clone());
+            simd::add_row(&mut vector, row);
+            simd::scalar::add_row(&mut scalar, row);
+            assert_eq!(vector, scalar);
+
+            simd::sub_row(&mut vector, row);
+            simd::scalar::sub_row(&mut scalar, row);
+            assert_eq!(vector, scalar);
+            assert_eq!(vector, values);
+
+            let weights = &network.output_weights[..HIDDEN];
+            assert_eq!(simd::crelu_dot(&values, weights), simd::scalar::crelu_dot(&values, weights));
+        }
+    }
+}
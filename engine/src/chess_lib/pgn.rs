@@ -0,0 +1,493 @@
+// PGN reader
+// see: http://www.saremba.de/chessgml/standards/pgn/pgn-complete.htm
+//
+// Reads tag pairs, SAN movetext with move numbers, `!`/`?` suffixes and `$n` NAGs, `{}` and `;` comments,
+// `%` escape lines and nested variations. Every move is checked against the legal moves of its position,
+// errors point at the line and column (both 1-based) of the offending token.
+
+use crate::board::*;
+
+use std::fmt;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnErrorKind {
+    UnterminatedString,
+    UnterminatedComment,
+    UnterminatedVariation,
+    UnterminatedTag,
+    InvalidTag,
+    InvalidFen(String),
+    UnexpectedCharacter(char),
+    UnexpectedVariationEnd,
+    UnexpectedResult(String),
+    VariationWithoutMove,
+    InvalidMove(SanError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: PgnErrorKind,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PGN error at line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            PgnErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            PgnErrorKind::UnterminatedComment => write!(f, "unterminated comment"),
+            PgnErrorKind::UnterminatedVariation => write!(f, "unterminated variation"),
+            PgnErrorKind::UnterminatedTag => write!(f, "unterminated tag pair"),
+            PgnErrorKind::InvalidTag => write!(f, "invalid tag pair (expected [Name \"value\"])"),
+            PgnErrorKind::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+            PgnErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            PgnErrorKind::UnexpectedVariationEnd => write!(f, "')' without a matching '('"),
+            PgnErrorKind::UnexpectedResult(r) => write!(f, "game result {} inside a variation", r),
+            PgnErrorKind::VariationWithoutMove => write!(f, "variation before the first move"),
+            PgnErrorKind::InvalidMove(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for PgnError {}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    pub mv: ChessMove,
+    pub san: String, // as written, without suffix annotations
+    pub nags: Vec<u8>, // `!` = 1, `?` = 2, `!!` = 3, `??` = 4, `!?` = 5, `?!` = 6, or any `$n`
+    pub comments_before: Vec<String>, // only used for the first move of a line
+    pub comments: Vec<String>,
+    pub variations: Vec<Vec<PgnMove>>, // alternatives to this move, played from the same position
+}
+
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>, // in file order
+    pub start: Board,
+    pub moves: Vec<PgnMove>, // main line
+    pub result: String, // "1-0", "0-1", "1/2-1/2" or "*"
+}
+
+impl PgnGame {
+    /// Parses a text holding exactly one game (anything after the first game is ignored).
+    pub fn parse(text: &str) -> Result<PgnGame, PgnError> {
+        PgnReader::new(text).next().unwrap_or_else(|| Ok(PgnGame {
+            tags: Vec::new(),
+            start: Board::new_start_pos(),
+            moves: Vec::new(),
+            result: "*".to_string(),
+        }))
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    TagOpen,
+    TagClose,
+    String(String),
+    Symbol(String), // SAN, move numbers, results and tag names
+    Glyph(u8),      // `!`/`?` suffixes and `$n`
+    Comment(String),
+    Period,
+    VariationOpen,
+    VariationClose,
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize, // byte offset
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek_char(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek_char()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, line: usize, column: usize, kind: PgnErrorKind) -> PgnError {
+        PgnError { line, column, kind }
+    }
+
+    /// Next token with its line and column, `None` at the end of the text.
+    fn next_token(&mut self) -> Result<Option<(Token, usize, usize)>, PgnError> {
+        loop {
+            let (line, column) = (self.line, self.column);
+            let Some(c) = self.peek_char() else {
+                return Ok(None);
+            };
+
+            let token = match c {
+                c if c.is_whitespace() => {
+                    self.bump();
+                    continue;
+                }
+                '%' if column == 1 => {
+                    // Escape mechanism: the whole line is ignored
+                    while self.bump().is_some_and(|c| c != '\n') {}
+                    continue;
+                }
+                '[' => { self.bump(); Token::TagOpen }
+                ']' => { self.bump(); Token::TagClose }
+                '(' => { self.bump(); Token::VariationOpen }
+                ')' => { self.bump(); Token::VariationClose }
+                '.' => { self.bump(); Token::Period }
+                '*' => { self.bump(); Token::Symbol("*".to_string()) }
+                '"' => {
+                    self.bump();
+                    let mut value = String::new();
+                    loop {
+                        match self.bump() {
+                            Some('"') => break,
+                            Some('\\') => match self.bump() {
+                                Some(c) => value.push(c),
+                                None => return Err(self.error(line, column, PgnErrorKind::UnterminatedString)),
+                            },
+                            Some('\n') | None => return Err(self.error(line, column, PgnErrorKind::UnterminatedString)),
+                            Some(c) => value.push(c),
+                        }
+                    }
+                    Token::String(value)
+                }
+                '{' => {
+                    self.bump();
+                    let mut comment = String::new();
+                    loop {
+                        match self.bump() {
+                            Some('}') => break,
+                            Some(c) => comment.push(c),
+                            None => return Err(self.error(line, column, PgnErrorKind::UnterminatedComment)),
+                        }
+                    }
+                    Token::Comment(comment.trim().to_string())
+                }
+                ';' => {
+                    self.bump();
+                    let mut comment = String::new();
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                        comment.push(c);
+                    }
+                    Token::Comment(comment.trim().to_string())
+                }
+                '$' => {
+                    self.bump();
+                    let mut digits = String::new();
+                    while let Some(c) = self.peek_char().filter(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                        self.bump();
+                    }
+                    match digits.parse() {
+                        Ok(nag) => Token::Glyph(nag),
+                        Err(_) => return Err(self.error(line, column, PgnErrorKind::UnexpectedCharacter('$'))),
+                    }
+                }
+                '!' | '?' => {
+                    let mut glyph = String::new();
+                    while let Some(c) = self.peek_char().filter(|&c| c == '!' || c == '?') {
+                        glyph.push(c);
+                        self.bump();
+                    }
+                    match glyph.as_str() {
+                        "!" => Token::Glyph(1),
+                        "?" => Token::Glyph(2),
+                        "!!" => Token::Glyph(3),
+                        "??" => Token::Glyph(4),
+                        "!?" => Token::Glyph(5),
+                        "?!" => Token::Glyph(6),
+                        _ => return Err(self.error(line, column, PgnErrorKind::UnexpectedCharacter(c))),
+                    }
+                }
+                c if c.is_ascii_alphanumeric() => {
+                    let mut symbol = String::new();
+                    while let Some(c) = self.peek_char().filter(|&c| c.is_ascii_alphanumeric() || "_+#=:-/".contains(c)) {
+                        symbol.push(c);
+                        self.bump();
+                    }
+                    Token::Symbol(symbol)
+                }
+                c => return Err(self.error(line, column, PgnErrorKind::UnexpectedCharacter(c))),
+            };
+            return Ok(Some((token, line, column)));
+        }
+    }
+
+    /// Skips to the next line starting with `[`, used to recover after an error.
+    fn skip_to_next_game(&mut self) {
+        while let Some(c) = self.bump() {
+            if c == '\n' && self.peek_char() == Some('[') {
+                return;
+            }
+        }
+    }
+}
+
+
+/// Iterates over the games of a PGN text. After an error the reader continues with the next game,
+/// so one broken game does not hide the rest of a large file.
+pub struct PgnReader<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<(Token, usize, usize)>,
+}
+
+impl<'a> PgnReader<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        PgnReader { lexer: Lexer { text, pos: 0, line: 1, column: 1 }, peeked: None }
+    }
+
+    fn peek(&mut self) -> Result<Option<&(Token, usize, usize)>, PgnError> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next_token()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next_token(&mut self) -> Result<Option<(Token, usize, usize)>, PgnError> {
+        match self.peeked.take() {
+            Some(token) => Ok(Some(token)),
+            None => self.lexer.next_token(),
+        }
+    }
+
+    fn parse_game(&mut self) -> Result<Option<PgnGame>, PgnError> {
+        let mut tags = Vec::new();
+        let mut fen_position = (0, 0);
+        while let Some((Token::TagOpen, line, column)) = self.peek()?.cloned() {
+            self.next_token()?;
+            let invalid = PgnError { line, column, kind: PgnErrorKind::InvalidTag };
+            let Some((Token::Symbol(name), ..)) = self.next_token()? else {
+                return Err(invalid);
+            };
+            let Some((Token::String(value), ..)) = self.next_token()? else {
+                return Err(invalid);
+            };
+            match self.next_token()? {
+                Some((Token::TagClose, ..)) => {}
+                _ => return Err(PgnError { line, column, kind: PgnErrorKind::UnterminatedTag }),
+            }
+            if name == "FEN" {
+                fen_position = (line, column);
+            }
+            tags.push((name, value));
+        }
+
+        if tags.is_empty() && self.peek()?.is_none() {
+            return Ok(None);
+        }
+
+        let start = match tags.iter().rev().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => {
+                let board = fen.parse::<Fen>().map_err(|e| e.to_string()).and_then(|fen| Board::from_fen(&fen).map_err(|e| e.to_string()));
+                let (line, column) = fen_position;
+                board.map_err(|e| PgnError { line, column, kind: PgnErrorKind::InvalidFen(e) })?
+            }
+            None => Board::new_start_pos(),
+        };
+
+        let mut result = None;
+        let moves = self.parse_line(&start, 0, &mut result)?;
+        let result = result
+            .or_else(|| tags.iter().find(|(name, _)| name == "Result").map(|(_, r)| r.clone()))
+            .unwrap_or_else(|| "*".to_string());
+
+        Ok(Some(PgnGame { tags, start, moves, result }))
+    }
+
+    /// Parses moves from `board` until the end of the variation (`depth` > 0) or of the game.
+    fn parse_line(&mut self, board: &Board, depth: usize, result: &mut Option<String>) -> Result<Vec<PgnMove>, PgnError> {
+        let mut moves: Vec<PgnMove> = Vec::new();
+        let mut board = board.clone();
+        let mut previous = board.clone(); // position before the last move, where its variations start
+        let mut pending_comments = Vec::new();
+
+        loop {
+            let Some((token, line, column)) = self.peek()?.cloned() else {
+                if depth > 0 {
+                    return Err(PgnError { line: self.lexer.line, column: self.lexer.column, kind: PgnErrorKind::UnterminatedVariation });
+                }
+                break;
+            };
+            // A tag at the top level starts the next game, even if this one had no result
+            if token == Token::TagOpen && depth == 0 {
+                break;
+            }
+            self.next_token()?;
+
+            match token {
+                Token::Period => {}
+                Token::Symbol(symbol) if symbol.chars().all(|c| c.is_ascii_digit()) => {} // move number
+                Token::Symbol(symbol) if matches!(symbol.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") => {
+                    if depth > 0 {
+                        return Err(PgnError { line, column, kind: PgnErrorKind::UnexpectedResult(symbol) });
+                    }
+                    *result = Some(symbol);
+                    break;
+                }
+                Token::Symbol(san) => {
+                    let mv = board.parse_san(&san).map_err(|e| PgnError { line, column, kind: PgnErrorKind::InvalidMove(e) })?;
+                    previous = board.clone();
+                    board = board.make_move(&mv);
+                    moves.push(PgnMove {
+                        mv,
+                        san,
+                        nags: Vec::new(),
+                        comments_before: std::mem::take(&mut pending_comments),
+                        comments: Vec::new(),
+                        variations: Vec::new(),
+                    });
+                }
+                Token::Glyph(nag) => match moves.last_mut() {
+                    Some(last) => last.nags.push(nag),
+                    None => return Err(PgnError { line, column, kind: PgnErrorKind::UnexpectedCharacter(if nag == 2 { '?' } else { '!' }) }),
+                },
+                Token::Comment(comment) => match moves.last_mut() {
+                    Some(last) => last.comments.push(comment),
+                    None => pending_comments.push(comment),
+                },
+                Token::VariationOpen => {
+                    if moves.is_empty() {
+                        return Err(PgnError { line, column, kind: PgnErrorKind::VariationWithoutMove });
+                    }
+                    let variation = self.parse_line(&previous, depth + 1, result)?;
+                    moves.last_mut().unwrap().variations.push(variation);
+                }
+                Token::VariationClose => {
+                    if depth == 0 {
+                        return Err(PgnError { line, column, kind: PgnErrorKind::UnexpectedVariationEnd });
+                    }
+                    break;
+                }
+                Token::TagOpen | Token::TagClose | Token::String(_) => {
+                    let c = match token { Token::TagOpen => '[', Token::TagClose => ']', _ => '"' };
+                    return Err(PgnError { line, column, kind: PgnErrorKind::UnexpectedCharacter(c) });
+                }
+            }
+        }
+        Ok(moves)
+    }
+}
+
+impl<'a> Iterator for PgnReader<'a> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.parse_game() {
+            Ok(game) => game.map(Ok),
+            Err(e) => {
+                self.peeked = None;
+                self.lexer.skip_to_next_game();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<Token> {
+        let mut lexer = Lexer { text, pos: 0, line: 1, column: 1 };
+        let mut tokens = Vec::new();
+        while let Some((token, ..)) = lexer.next_token().unwrap() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    #[test]
+    fn lexes_tags_moves_comments_and_glyphs() {
+        let symbol = |s: &str| Token::Symbol(s.to_string());
+        assert_eq!(tokens("[Event \"A \\\"quoted\\\" name\"]"),
+                   vec![Token::TagOpen, symbol("Event"), Token::String("A \"quoted\" name".to_string()), Token::TagClose]);
+        assert_eq!(tokens("1. e4 {best by test} e5!? 2.Nf3 $14 ; rest of line\n(2... d6) 1-0"),
+                   vec![symbol("1"), Token::Period, symbol("e4"), Token::Comment("best by test".to_string()), symbol("e5"),
+                        Token::Glyph(5), symbol("2"), Token::Period, symbol("Nf3"), Token::Glyph(14),
+                        Token::Comment("rest of line".to_string()), Token::VariationOpen, symbol("2"), Token::Period,
+                        Token::Period, Token::Period, symbol("d6"), Token::VariationClose, symbol("1-0")]);
+        assert_eq!(tokens("e8=Q+ O-O-O# 1/2-1/2 *"), vec![symbol("e8=Q+"), symbol("O-O-O#"), symbol("1/2-1/2"), symbol("*")]);
+    }
+
+    #[test]
+    fn skips_escape_lines_only_at_the_start_of_a_line() {
+        assert_eq!(tokens("% ignored e4\ne4"), vec![Token::Symbol("e4".to_string())]);
+        let mut lexer = Lexer { text: "e4 %", pos: 0, line: 1, column: 1 };
+        lexer.next_token().unwrap();
+        assert_eq!(lexer.next_token().unwrap_err().kind, PgnErrorKind::UnexpectedCharacter('%'));
+    }
+
+    #[test]
+    fn reports_lexer_errors_with_their_position() {
+        let error = |text: &str| {
+            let mut lexer = Lexer { text, pos: 0, line: 1, column: 1 };
+            loop {
+                match lexer.next_token() {
+                    Ok(Some(_)) => continue,
+                    Ok(None) => panic!("no error in {:?}", text),
+                    Err(e) => return e,
+                }
+            }
+        };
+        assert_eq!(error("e4\n  {open"), PgnError { line: 2, column: 3, kind: PgnErrorKind::UnterminatedComment });
+        assert_eq!(error("[Event \"x]"), PgnError { line: 1, column: 8, kind: PgnErrorKind::UnterminatedString });
+        assert_eq!(error("e4 !!!"), PgnError { line: 1, column: 4, kind: PgnErrorKind::UnexpectedCharacter('!') });
+        assert_eq!(error("e4 &"), PgnError { line: 1, column: 4, kind: PgnErrorKind::UnexpectedCharacter('&') });
+    }
+
+    #[test]
+    fn reads_a_game_with_variations_and_annotations() {
+        let text = "[Event \"Test\"]\n[WhiteElo \"2100\"]\n\n{opening} 1. e4 e5 (1... c5 2. Nf3 ({idea} 2. c3)) 2. Nf3?! $1 {develops} 1-0";
+        let game = PgnGame::parse(text).unwrap();
+        assert!(game.tags.contains(&("WhiteElo".to_string(), "2100".to_string())));
+        assert_eq!(game.result, "1-0");
+        let sans: Vec<&str> = game.moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(sans, ["e4", "e5", "Nf3"]);
+        assert_eq!(game.moves[0].comments_before, ["opening"]);
+        assert_eq!(game.moves[2].nags, [6, 1]);
+        assert_eq!(game.moves[2].comments, ["develops"]);
+
+        let sicilian = &game.moves[1].variations[0];
+        assert_eq!(sicilian[0].mv.to_uci(), "c7c5");
+        assert_eq!(sicilian[1].variations[0][0].comments_before, ["idea"]);
+        assert_eq!(sicilian[1].variations[0][0].mv.to_uci(), "c2c3");
+    }
+
+    #[test]
+    fn continues_with_the_next_game_after_an_error() {
+        let text = "[Event \"Bad\"]\n\n1. e4 e4 *\n\n[Event \"Good\"]\n\n1. d4 *\n";
+        let games: Vec<_> = PgnReader::new(text).collect();
+        assert_eq!(games.len(), 2);
+        let error = games[0].as_ref().unwrap_err();
+        assert_eq!((error.line, error.column), (3, 7));
+        assert!(matches!(error.kind, PgnErrorKind::InvalidMove(SanError::IllegalMove(_))));
+        assert_eq!(games[1].as_ref().unwrap().moves[0].mv.to_uci(), "d2d4");
+    }
+
+    #[test]
+    fn rejects_unbalanced_variations() {
+        assert_eq!(PgnGame::parse("1. e4 ) *").unwrap_err().kind, PgnErrorKind::UnexpectedVariationEnd);
+        assert_eq!(PgnGame::parse("1. e4 (1. d4").unwrap_err().kind, PgnErrorKind::UnterminatedVariation);
+        assert_eq!(PgnGame::parse("( 1. e4 ) *").unwrap_err().kind, PgnErrorKind::VariationWithoutMove);
+    }
+}
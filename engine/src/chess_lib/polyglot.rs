@@ -0,0 +1,294 @@
+// Polyglot opening books
+// see: http://hgm.nubati.net/book_format.html
+//
+// A book is a list of 16 byte big-endian entries sorted by key:
+//   u64 position key, u16 move, u16 weight, u32 learn (unused)
+// Moves pack to file (bits 0-2), to rank (3-5), from file (6-8), from rank (9-11) and the promotion piece (12-14, 1 = knight .. 4 = queen).
+// Castling is written as the king capturing its own rook (e1h1), never as e1g1.
+
+use crate::board::*;
+use crate::polyglot_keys::*;
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use std::sync::{Arc, RwLock};
+use std::fmt;
+
+const BOOK_ENTRY_SIZE: usize = 16;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookError {
+    Io(String),
+    InvalidLength(usize),
+    Empty,
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::Io(e) => write!(f, "Could not read opening book: {}", e),
+            BookError::InvalidLength(n) => write!(f, "Opening book size {} is not a multiple of {} bytes", n, BOOK_ENTRY_SIZE),
+            BookError::Empty => write!(f, "Opening book has no entries"),
+        }
+    }
+}
+impl std::error::Error for BookError {}
+
+
+impl Board {
+    /// Position key as defined by the Polyglot book format.
+    pub fn polyglot_key(&self) -> u64 {
+        let mut key = 0u64;
+
+        for piece in PieceType::ALL {
+            for color in [Color::White, Color::Black] {
+                let kind = 2 * piece as usize + (color == Color::White) as usize;
+                let bb = self.piece_bbs[piece as usize] & self.color_bbs[color as usize];
+                for sq in bb.iter() {
+                    key ^= POLYGLOT_RANDOM64[64 * kind + sq as usize];
+                }
+            }
+        }
+
+        let rights = [
+            CastlingRights::WHITE_KINGSIDE,
+            CastlingRights::WHITE_QUEENSIDE,
+            CastlingRights::BLACK_KINGSIDE,
+            CastlingRights::BLACK_QUEENSIDE,
+        ];
+        for (i, right) in rights.into_iter().enumerate() {
+            if self.castling_rights.has_right(right) {
+                key ^= POLYGLOT_RANDOM64[POLYGLOT_CASTLING_OFFSET + i];
+            }
+        }
+
+        // The en passant file only counts if a pawn of the side to move could actually capture
+        if let Some(sq) = self.en_passant_square {
+            let capturers = PRECOMPUTED.pawn_attacks[self.turn.opponent() as usize][sq as usize]
+                & self.piece_bbs[PieceType::Pawn as usize]
+                & self.color_bbs[self.turn as usize];
+            if capturers.is_not_empty() {
+                key ^= POLYGLOT_RANDOM64[POLYGLOT_EN_PASSANT_OFFSET + sq.file() as usize];
+            }
+        }
+
+        if self.turn == Color::White {
+            key ^= POLYGLOT_RANDOM64[POLYGLOT_TURN_OFFSET];
+        }
+
+        key
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    /// Encodes a legal move of `board` the way Polyglot stores it.
+    pub fn encode_move(board: &Board, mv: &ChessMove) -> u16 {
+        let from = mv.from();
+        let to = match (board.piece_on_square(from), from, mv.to()) {
+            (Some((PieceType::King, _)), Square::E1, Square::G1) => Square::H1,
+            (Some((PieceType::King, _)), Square::E1, Square::C1) => Square::A1,
+            (Some((PieceType::King, _)), Square::E8, Square::G8) => Square::H8,
+            (Some((PieceType::King, _)), Square::E8, Square::C8) => Square::A8,
+            (_, _, to) => to,
+        };
+        let promotion = match mv.promotion() {
+            Some(PieceType::Knight) => 1,
+            Some(PieceType::Bishop) => 2,
+            Some(PieceType::Rook) => 3,
+            Some(PieceType::Queen) => 4,
+            _ => 0,
+        };
+        to as u16 | (from as u16) << 6 | promotion << 12
+    }
+
+    /// The entry's move as a legal move in `board`, `None` if the book is wrong about this position.
+    pub fn to_move(self, board: &Board) -> Option<ChessMove> {
+        let to = Square::from_u8((self.mv & 0x3F) as u8);
+        let from = Square::from_u8(((self.mv >> 6) & 0x3F) as u8);
+        let promotion = match (self.mv >> 12) & 0x7 {
+            0 => None,
+            1 => Some(PieceType::Knight),
+            2 => Some(PieceType::Bishop),
+            3 => Some(PieceType::Rook),
+            4 => Some(PieceType::Queen),
+            _ => return None,
+        };
+
+        // King takes own rook -> the usual two square king move
+        let to = match (board.piece_on_square(from), from, to) {
+            (Some((PieceType::King, _)), Square::E1, Square::H1) => Square::G1,
+            (Some((PieceType::King, _)), Square::E1, Square::A1) => Square::C1,
+            (Some((PieceType::King, _)), Square::E8, Square::H8) => Square::G8,
+            (Some((PieceType::King, _)), Square::E8, Square::A8) => Square::C8,
+            _ => to,
+        };
+
+        let mut pseudo_legal_moves = Vec::with_capacity(200);
+        let mut legal_moves = Vec::with_capacity(100);
+        board.generate_legal_moves(&mut pseudo_legal_moves, &mut legal_moves);
+        legal_moves.into_iter().find(|mv| mv.from() == from && mv.to() == to && mv.promotion() == promotion)
+    }
+}
+
+
+pub struct PolyglotBook {
+    entries: Vec<BookEntry>,
+}
+
+impl fmt::Debug for PolyglotBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PolyglotBook {{ entries: {} }}", self.entries.len())
+    }
+}
+
+impl PolyglotBook {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if !bytes.len().is_multiple_of(BOOK_ENTRY_SIZE) {
+            return Err(BookError::InvalidLength(bytes.len()));
+        }
+        if bytes.is_empty() {
+            return Err(BookError::Empty);
+        }
+
+        let mut entries: Vec<BookEntry> = bytes.chunks_exact(BOOK_ENTRY_SIZE).map(|c| BookEntry {
+            key: u64::from_be_bytes(c[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes([c[8], c[9]]),
+            weight: u16::from_be_bytes([c[10], c[11]]),
+            learn: u32::from_be_bytes(c[12..16].try_into().unwrap()),
+        }).collect();
+
+        // Books are supposed to be sorted already, a stable sort keeps the order of each position's moves
+        if !entries.is_sorted_by_key(|e| e.key) {
+            entries.sort_by_key(|e| e.key);
+        }
+        Ok(PolyglotBook { entries })
+    }
+
+    /// Builds a book from entries in any order.
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Self {
+        entries.sort_by_key(|e| (e.key, std::cmp::Reverse(e.weight)));
+        PolyglotBook { entries }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * BOOK_ENTRY_SIZE);
+        for e in &self.entries {
+            bytes.extend_from_slice(&e.key.to_be_bytes());
+            bytes.extend_from_slice(&e.mv.to_be_bytes());
+            bytes.extend_from_slice(&e.weight.to_be_bytes());
+            bytes.extend_from_slice(&e.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_file(path: &str) -> Result<Self, BookError> {
+        let bytes = std::fs::read(path).map_err(|e| BookError::Io(format!("{}: {}", path, e)))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// All entries for a position key.
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|e| e.key < key);
+        let end = start + self.entries[start..].partition_point(|e| e.key == key);
+        &self.entries[start..end]
+    }
+
+    /// Legal book moves for `board` with their weights. Moves with weight 0 are never played by Polyglot and are left out.
+    pub fn moves(&self, board: &Board) -> Vec<(ChessMove, u16)> {
+        self.entries(board.polyglot_key()).iter()
+            .filter(|e| e.weight > 0)
+            .filter_map(|e| Some((e.to_move(board)?, e.weight)))
+            .collect()
+    }
+
+    /// The book move with the highest weight.
+    pub fn best_move(&self, board: &Board) -> Option<ChessMove> {
+        self.moves(board).into_iter().max_by_key(|&(_, weight)| weight).map(|(mv, _)| mv)
+    }
+
+    /// Picks one of the book moves with a probability proportional to its weight.
+    pub fn pick_move<R: Rng + ?Sized>(&self, board: &Board, rng: &mut R) -> Option<ChessMove> {
+        let moves = self.moves(board);
+        let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut roll = rng.random_range(0..total);
+        for (mv, weight) in moves {
+            if roll < weight as u32 {
+                return Some(mv);
+            }
+            roll -= weight as u32;
+        }
+        None
+    }
+}
+
+
+static OPENING_BOOK: Lazy<RwLock<Option<Arc<PolyglotBook>>>> = Lazy::new(|| RwLock::new(None));
+
+pub fn opening_book() -> Option<Arc<PolyglotBook>> {
+    OPENING_BOOK.read().unwrap().clone()
+}
+
+pub fn set_opening_book(book: Option<PolyglotBook>) {
+    *OPENING_BOOK.write().unwrap() = book.map(Arc::new);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(moves: &[&str]) -> Board {
+        let mut board = Board::new_start_pos();
+        for mv in moves {
+            let mv = board.parse_uci_move(mv).expect("test moves are legal");
+            board = board.make_move(&mv);
+        }
+        board
+    }
+
+    // Reference keys from the Polyglot book format specification
+    #[test]
+    fn polyglot_keys_match_the_specification() {
+        let cases: [(&[&str], u64); 9] = [
+            (&[], 0x463b96181691fc9c),
+            (&["e2e4"], 0x823c9b50fd114196),
+            (&["e2e4", "d7d5"], 0x0756b94461c50fb0),
+            (&["e2e4", "d7d5", "e4e5"], 0x662fafb965db29d4),
+            (&["e2e4", "d7d5", "e4e5", "f7f5"], 0x22a48b5a8e47ff78),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"], 0x652a607ca3f242c1),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"], 0x00fdd303c946bdd9),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"], 0x3c8123ea7b067637),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"], 0x5c3f9b829b279560),
+        ];
+        for (moves, key) in cases {
+            assert_eq!(play(moves).polyglot_key(), key, "after {:?}", moves);
+        }
+    }
+
+    #[test]
+    fn book_moves_round_trip_through_the_encoding() {
+        let board = Board::new_start_pos();
+        let mv = board.parse_uci_move("g1f3").unwrap();
+        let entry = BookEntry { key: board.polyglot_key(), mv: BookEntry::encode_move(&board, &mv), weight: 1, learn: 0 };
+        assert_eq!(entry.to_move(&board), Some(mv));
+    }
+}
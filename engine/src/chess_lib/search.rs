@@ -1,9 +1,17 @@
 use crate::board::*;
 use crate::terminal_states::*;
 use crate::game::*;
+use crate::eval_params::*;
+use crate::time_manager::*;
+use crate::send_response;
+#[cfg(target_arch = "wasm32")]
+use crate::engine::GLOBAL_RESPONSE_SENDER;
 use std::cmp::{max, min};
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
 
+const PROGRESS_INTERVAL_MS: u64 = 1000;
+const CURRMOVE_DELAY_MS: u64 = 1000; // short searches do not report their root moves
+
 pub fn get_qsearch_piece_value(piece_type: PieceType) -> i32 {
     match piece_type {
         PieceType::Pawn => 100,
@@ -15,13 +23,71 @@ pub fn get_qsearch_piece_value(piece_type: PieceType) -> i32 {
     }
 }
 
+/// One line of a multi-PV search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PvLine {
+    pub score: i32, // from White's perspective
+    pub moves: Vec<ChessMove>, // starts with the root move
+}
+
 impl Game {
+    /// True once the search was asked to stop or has used up its node budget.
+    #[inline]
+    pub fn should_stop(&self) -> bool {
+        self.stop_signal.load(Ordering::Relaxed) || self.node_limit.is_some_and(|limit| self.nodes >= limit) || self.past_deadline()
+    }
+
+    /// Checks the clock every 1024 nodes. Raises `stop_signal` once the deadline has passed, so the search stays stopped.
+    #[inline]
+    fn past_deadline(&self) -> bool {
+        let deadline = self.deadline_ms.load(Ordering::Relaxed);
+        if deadline == NO_DEADLINE || !self.nodes.is_multiple_of(1024) || now_ms() < deadline {
+            return false;
+        }
+        self.stop_signal.store(true, Ordering::Relaxed);
+        true
+    }
+
+    /// Sends `info nodes nps hashfull time` at most once per `PROGRESS_INTERVAL_MS`.
+    fn send_progress(&mut self) {
+        if let Some(line) = self.progress_line(now_ms()) {
+            send_response!("{}", line);
+        }
+    }
+
+    /// The progress line at `now`, `None` if the last one was sent less than `PROGRESS_INTERVAL_MS` before.
+    fn progress_line(&mut self, now: u64) -> Option<String> {
+        if now < self.last_progress_ms + PROGRESS_INTERVAL_MS {
+            return None;
+        }
+        self.last_progress_ms = now;
+        let time = now.saturating_sub(self.search_start_ms);
+        let hashfull = self.transposition_table.len() * 1000 / self.transposition_table.size().max(1);
+        Some(format!("info nodes {} nps {} hashfull {} time {}", self.nodes, self.nodes * 1000 / time.max(1), hashfull, time))
+    }
+
+    /// Sends `info currmove currmovenumber` for a root move once the search has been running for `CURRMOVE_DELAY_MS`.
+    fn send_current_move(&mut self, depth: u8, mv: &ChessMove, number: usize) {
+        if let Some(line) = self.current_move_line(now_ms(), depth, mv, number) {
+            send_response!("{}", line);
+        }
+    }
+
+    fn current_move_line(&self, now: u64, depth: u8, mv: &ChessMove, number: usize) -> Option<String> {
+        (self.report_progress && now >= self.search_start_ms + CURRMOVE_DELAY_MS)
+            .then(|| format!("info depth {} currmove {} currmovenumber {}", depth, mv.to_uci(), number))
+    }
+
     /// The Alpha-Beta search algorithm.
     #[cfg_attr(feature = "tracy", tracing::instrument(skip_all))]
     pub fn alphabeta(&mut self, board: Board, depth: u8, mut alpha: i32, mut beta: i32) -> i32 {
-        if self.stop_signal.load(Ordering::Relaxed) {
+        if self.should_stop() {
             return 0; // Return a neutral score or a score indicating interruption
         }
+        self.nodes += 1;
+        if self.report_progress && self.nodes.is_multiple_of(4096) {
+            self.send_progress();
+        }
         
         let original_alpha = alpha;
         let board_hash = board.compute_zobrist_hash();
@@ -43,7 +109,31 @@ impl Game {
             return 0;
         }
 
-        if let Some(entry) = self.transposition_table.get(&board_hash) {
+        // Drawn KPK positions are known exactly, no need to search them.
+        // Won ones are left to the search so that the pawn actually gets pushed (see evaluate_kpk).
+        if board.probe_kpk() == Some(false) {
+            *self.board_repetition_counts.get_mut(&board_hash).unwrap() -= 1;
+            self.transposition_table.insert(
+                board_hash,
+                TTEntry {
+                    score: 0, depth, node_type: NodeType::Exact, best_move: None,
+                },
+            );
+            return 0;
+        }
+
+        if let Some(score) = self.probe_dtm(&board).or_else(|| self.probe_tablebase_wdl(&board, depth)) {
+            *self.board_repetition_counts.get_mut(&board_hash).unwrap() -= 1;
+            self.transposition_table.insert(
+                board_hash,
+                TTEntry {
+                    score, depth, node_type: NodeType::Exact, best_move: None,
+                },
+            );
+            return score;
+        }
+
+        if let Some(entry) = self.transposition_table.get(board_hash) {
             if entry.depth >= depth {
                 // Check if TT hit causes immediate return
                 let mut tt_causes_return = false;
@@ -82,7 +172,7 @@ impl Game {
 
         // 4. Base Case (Terminal Node - other than repetition draw)
         if game_state != GameState::Ongoing {
-            let eval = board.evaluate(depth, &mut pseudo_legal_moves, &mut legal_moves, game_state);
+            let eval = board.evaluate(depth, &mut pseudo_legal_moves, &mut legal_moves, game_state, &self.eval_params);
             *self.board_repetition_counts.get_mut(&board_hash).unwrap() -= 1;
             self.pseudo_legal_moves_container[depth as usize] = pseudo_legal_moves;
             self.legal_moves_container[depth as usize] = legal_moves;
@@ -125,7 +215,7 @@ impl Game {
         }
 
         // 5. Move Ordering (for depth > 0)
-        let tt_best_move = self.transposition_table.get(&board_hash).and_then(|entry| entry.best_move);
+        let tt_best_move = self.transposition_table.get(board_hash).and_then(|entry| entry.best_move);
         legal_moves.sort_unstable_by_key(|mv| -self.score_move(mv, depth, tt_best_move));
 
         let mut result_value;
@@ -135,7 +225,7 @@ impl Game {
         if board.turn == Color::White { // Maximizing player
             let mut value = i32::MIN; // Negative infinity
             for mv in &legal_moves {
-                if self.stop_signal.load(Ordering::Relaxed) {
+                if self.should_stop() {
                     *self.board_repetition_counts.get_mut(&board_hash).unwrap() -= 1;
                     self.pseudo_legal_moves_container[depth as usize] = pseudo_legal_moves;
                     self.legal_moves_container[depth as usize] = legal_moves;
@@ -151,7 +241,9 @@ impl Game {
                 }
 
                 let new_board = board.make_move(&mv);
+                self.nnue_push(&board, mv);
                 let score = self.alphabeta(new_board, depth - 1, alpha, beta);
+                self.nnue_pop();
                 if score > value {
                     value = score;
                     best_move_for_tt = Some(*mv);
@@ -173,7 +265,7 @@ impl Game {
         } else { // Color::Black (Minimizing player)
             let mut value = i32::MAX; // Positive infinity
             for mv in &legal_moves {
-                if self.stop_signal.load(Ordering::Relaxed) {
+                if self.should_stop() {
                     *self.board_repetition_counts.get_mut(&board_hash).unwrap() -= 1;
                     self.pseudo_legal_moves_container[depth as usize] = pseudo_legal_moves;
                     self.legal_moves_container[depth as usize] = legal_moves;
@@ -189,7 +281,9 @@ impl Game {
                 }
 
                 let new_board = board.make_move(&mv);
+                self.nnue_push(&board, mv);
                 let score = self.alphabeta(new_board, depth - 1, alpha, beta);
+                self.nnue_pop();
                 if score < value {
                     value = score;
                     best_move_for_tt = Some(*mv);
@@ -237,9 +331,13 @@ impl Game {
         mut beta: i32,
         q_depth: u8, // Remaining quiescence search depth
     ) -> i32 {
-        if self.stop_signal.load(Ordering::Relaxed) {
+        if self.should_stop() {
             return 0; // Return a neutral score or a score indicating interruption
         }
+        self.nodes += 1;
+        if self.report_progress && self.nodes.is_multiple_of(4096) {
+            self.send_progress();
+        }
 
         // 1. Check quiescence depth limit
         if q_depth == 0 {
@@ -251,7 +349,7 @@ impl Game {
             let mut temp_legal = Vec::new();  // Placeholder
             // If evaluate *needs* accurate mobility for stand-pat, generate moves for `board` here:
             // board.generate_legal_moves(&mut temp_pseudo, &mut temp_legal);
-            return board.evaluate(0, &mut temp_pseudo, &mut temp_legal, GameState::Ongoing);
+            return self.static_eval(&board, &mut temp_pseudo, &mut temp_legal);
         }
 
         // 2. Stand-pat evaluation
@@ -259,7 +357,7 @@ impl Game {
         // For an accurate stand-pat that includes mobility, generate all legal moves for the current `board`.
         let mut stand_pat_pseudo_moves = Vec::new();
         let mut stand_pat_legal_moves = Vec::new();
-        let stand_pat_score = board.evaluate(0, &mut stand_pat_pseudo_moves, &mut stand_pat_legal_moves, GameState::Ongoing);
+        let stand_pat_score = self.static_eval(&board, &mut stand_pat_pseudo_moves, &mut stand_pat_legal_moves);
         board.generate_legal_moves(&mut stand_pat_pseudo_moves, &mut stand_pat_legal_moves);
 
 
@@ -310,13 +408,15 @@ impl Game {
         if board.turn == Color::White { // Maximizing player
             let mut current_best_score = stand_pat_score; // Initialize with stand-pat
             for mv in &tactical_moves {
-                if self.stop_signal.load(Ordering::Relaxed) {
+                if self.should_stop() {
                     return current_best_score; // Return the best score found so far
                 }
 
                 let new_board = board.make_move(&mv);
+                self.nnue_push(&board, mv);
                 // Recursively call qsearch for the new board state
                 let score = self.qsearch(new_board, alpha, beta, q_depth - 1);
+                self.nnue_pop();
                 current_best_score = max(current_best_score, score);
                 alpha = max(alpha, current_best_score);
                 if alpha >= beta {
@@ -327,13 +427,15 @@ impl Game {
         } else { // Minimizing player (Black)
             let mut current_best_score = stand_pat_score; // Initialize with stand-pat
             for mv in &tactical_moves {
-                if self.stop_signal.load(Ordering::Relaxed) {
+                if self.should_stop() {
                     return current_best_score; // Return the best score found so far
                 }
 
                 let new_board = board.make_move(&mv);
+                self.nnue_push(&board, mv);
                 // Recursively call qsearch for the new board state
                 let score = self.qsearch(new_board, alpha, beta, q_depth - 1);
+                self.nnue_pop();
                 current_best_score = min(current_best_score, score);
                 beta = min(beta, current_best_score);
                 if beta <= alpha {
@@ -424,14 +526,76 @@ impl Game {
     /// - `depth`: The maximum search depth.
     ///
     /// Returns: An `Option<ChessMove>` representing the best move found, or `None` if no legal moves.
-    #[cfg_attr(feature = "tracy", tracing::instrument(skip_all))]
     pub fn find_best_move(&mut self, depth: u8) -> Option<ChessMove> {
+        self.find_best_move_with_score(depth).map(|(mv, _)| mv)
+    }
+
+    /// Same as `find_best_move`, but also returns the move's score from White's perspective.
+    /// If the search was interrupted (see `should_stop`) the result only covers the moves searched so far.
+    pub fn find_best_move_with_score(&mut self, depth: u8) -> Option<(ChessMove, i32)> {
+        self.search_root(depth, &[])
+    }
+
+    /// The best `count` root moves, best first, each with its score from White's perspective and its principal variation.
+    /// Every line is a full root search that skips the moves of the lines found before it.
+    /// If the search was interrupted the last line only covers the moves searched so far.
+    pub fn find_best_lines(&mut self, depth: u8, count: usize) -> Vec<PvLine> {
+        let mut lines: Vec<PvLine> = Vec::with_capacity(count);
+        let mut excluded = Vec::with_capacity(count);
+        while lines.len() < count && !self.should_stop() {
+            let Some((mv, score)) = self.search_root(depth, &excluded) else {
+                break;
+            };
+            excluded.push(mv);
+            lines.push(PvLine { score, moves: self.principal_variation(mv, depth as usize) });
+        }
+        lines
+    }
+
+    /// `first` followed by the best moves stored in the transposition table, at most `max_len` moves.
+    pub fn principal_variation(&self, first: ChessMove, max_len: usize) -> Vec<ChessMove> {
+        let mut pv = vec![first];
+        let mut board = self.board.make_move(&first);
+        let mut seen = vec![board.compute_zobrist_hash()];
+        let mut pseudo_legal_moves = Vec::with_capacity(200);
+        let mut legal_moves = Vec::with_capacity(100);
+
+        while pv.len() < max_len {
+            let Some(mv) = self.transposition_table.get(board.compute_zobrist_hash()).and_then(|entry| entry.best_move) else {
+                break;
+            };
+            // The table is only keyed by hash, so make sure the move belongs to this position
+            board.generate_legal_moves(&mut pseudo_legal_moves, &mut legal_moves);
+            if !legal_moves.contains(&mv) {
+                break;
+            }
+            board = board.make_move(&mv);
+            let hash = board.compute_zobrist_hash();
+            if seen.contains(&hash) {
+                break;
+            }
+            seen.push(hash);
+            pv.push(mv);
+        }
+        pv
+    }
+
+    /// Root search skipping the moves in `excluded`.
+    #[cfg_attr(feature = "tracy", tracing::instrument(skip_all))]
+    fn search_root(&mut self, depth: u8, excluded: &[ChessMove]) -> Option<(ChessMove, i32)> {
         // Error check for initial search depth.
         if depth > self.max_search_depth {
             eprintln!("Error: Initial search depth ({}) exceeds the engine's configured max_depth ({}).", depth, self.max_search_depth);
             return None;
         }
 
+        if self.use_loaded_eval_params {
+            self.eval_params = eval_params();
+        }
+        self.nnue_begin_search();
+        self.syzygy_begin_search();
+        self.dtm_begin_search();
+
         // Retrieve move containers for the initial depth.
         let mut pseudo_legal_moves = std::mem::take(&mut self.pseudo_legal_moves_container[depth as usize]);
         let mut legal_moves = std::mem::take(&mut self.legal_moves_container[depth as usize]);
@@ -444,9 +608,17 @@ impl Game {
             return None;
         }
 
+        // Only search the moves that keep the best tablebase result.
+        self.filter_tablebase_root_moves(&mut legal_moves);
+        legal_moves.retain(|mv| !excluded.contains(mv));
+        if !self.search_moves.is_empty() {
+            // Compared without the move flags, the moves may come straight from UCI strings
+            legal_moves.retain(|mv| self.search_moves.iter().any(|m| m.from() == mv.from() && m.to() == mv.to() && m.promotion() == mv.promotion()));
+        }
+
         // Get TT best move for initial move ordering at the root.
         let board_hash = self.board.compute_zobrist_hash();
-        let tt_best_move = self.transposition_table.get(&board_hash).and_then(|entry| entry.best_move);
+        let tt_best_move = self.transposition_table.get(board_hash).and_then(|entry| entry.best_move);
 
         // Sort moves using the enhanced scoring function to prioritize good moves.
         legal_moves.sort_unstable_by_key(|mv| -self.score_move(mv, depth, tt_best_move));
@@ -458,29 +630,45 @@ impl Game {
         // Perform the Alpha-Beta search for the root node.
         if self.board.turn == Color::White { // Maximizing player
             let mut best_score = i32::MIN;
-            for mv in legal_moves {
-                if self.stop_signal.load(Ordering::Relaxed) {
+            for (i, mv) in legal_moves.into_iter().enumerate() {
+                if self.should_stop() {
                     return best; // Return the best move found so far if interrupted
                 }
-
-                let score = self.alphabeta(self.board.make_move(&mv), depth - 1, alpha, beta);
+                self.send_current_move(depth, &mv, i + 1);
+
+                let board = self.board.clone();
+                self.nnue_push(&board, &mv);
+                let score = self.alphabeta(board.make_move(&mv), depth - 1, alpha, beta);
+                self.nnue_pop();
+                if self.should_stop() {
+                    // The score of an interrupted move is meaningless, it is only used if no move was searched fully
+                    return best.or(Some((mv, score)));
+                }
                 if score > best_score {
                     best_score = score;
-                    best = Some(mv);
+                    best = Some((mv, score));
                 }
                 alpha = max(alpha, best_score); // Update alpha for the root node
             }
         } else { // Color::Black (Minimizing player)
             let mut best_score = i32::MAX;
-            for mv in legal_moves {
-                if self.stop_signal.load(Ordering::Relaxed) {
+            for (i, mv) in legal_moves.into_iter().enumerate() {
+                if self.should_stop() {
                     return best; // Return the best move found so far if interrupted
                 }
+                self.send_current_move(depth, &mv, i + 1);
                 
-                let score = self.alphabeta(self.board.make_move(&mv), depth - 1, alpha, beta);
+                let board = self.board.clone();
+                self.nnue_push(&board, &mv);
+                let score = self.alphabeta(board.make_move(&mv), depth - 1, alpha, beta);
+                self.nnue_pop();
+                if self.should_stop() {
+                    // The score of an interrupted move is meaningless, it is only used if no move was searched fully
+                    return best.or(Some((mv, score)));
+                }
                 if score < best_score {
                     best_score = score;
-                    best = Some(mv);
+                    best = Some((mv, score));
                 }
                 beta = min(beta, best_score); // Update beta for the root node
             }
@@ -488,4 +676,46 @@ impl Game {
 
         best
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game() -> Game {
+        let mut game = Game::new(Board::new_start_pos(), 5, 3, Arc::new(AtomicBool::new(false)));
+        game.report_progress = true;
+        game.search_start_ms = 10_000;
+        game.last_progress_ms = 10_000;
+        game
+    }
+
+    #[test]
+    fn progress_lines_are_sent_once_per_interval() {
+        let mut game = game();
+        game.nodes = 30_000;
+        assert_eq!(game.progress_line(10_999), None);
+        assert_eq!(game.progress_line(12_000).as_deref(), Some("info nodes 30000 nps 15000 hashfull 0 time 2000"));
+        assert_eq!(game.progress_line(12_500), None);
+        assert_eq!(game.last_progress_ms, 12_000);
+        assert!(game.progress_line(13_000).is_some());
+    }
+
+    #[test]
+    fn current_moves_are_only_reported_by_long_searches() {
+        let mut game = game();
+        let mv = game.board.parse_uci_move("e2e4").unwrap();
+        assert_eq!(game.current_move_line(10_500, 4, &mv, 1), None);
+        assert_eq!(game.current_move_line(11_000, 4, &mv, 3).as_deref(), Some("info depth 4 currmove e2e4 currmovenumber 3"));
+        game.report_progress = false;
+        assert_eq!(game.current_move_line(20_000, 4, &mv, 3), None);
+    }
+
+    #[test]
+    fn searches_without_progress_reporting_still_find_moves() {
+        let mut game = game();
+        game.report_progress = false;
+        assert!(game.find_best_move(3).is_some());
+        assert_eq!(game.last_progress_ms, 10_000);
+    }
+}
@@ -0,0 +1,189 @@
+// Syzygy endgame tablebases, probed through shakmaty-syzygy
+// see: https://www.chessprogramming.org/Syzygy_Bases
+//
+// Only available natively, the browser build never loads any tables.
+// Positions are handed to the prober as FEN, which is cheap next to the file access of a probe.
+
+use crate::board::*;
+use crate::game::*;
+
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+use std::fmt;
+
+#[cfg(not(target_arch = "wasm32"))]
+use shakmaty::{fen::Fen, CastlingMode, Chess, Position};
+
+/// Score of a tablebase win, above any evaluation but below mate scores.
+pub const TB_WIN_SCORE: i32 = 500_000;
+
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TablebaseError {
+    Io(String),
+    NoTables(String),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TablebaseError::Io(e) => write!(f, "Could not read tablebase directory: {}", e),
+            TablebaseError::NoTables(path) => write!(f, "No Syzygy tables found in {}", path),
+        }
+    }
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl std::error::Error for TablebaseError {}
+
+
+pub struct Tablebases {
+    #[cfg(not(target_arch = "wasm32"))]
+    tables: shakmaty_syzygy::Tablebase<Chess>,
+    pub max_pieces: u32,
+    pub file_count: usize,
+}
+
+impl fmt::Debug for Tablebases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tablebases {{ max_pieces: {}, files: {} }}", self.max_pieces, self.file_count)
+    }
+}
+
+impl Tablebases {
+    /// Opens every table in `path`, which may list several directories separated like `PATH`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: &str) -> Result<Self, TablebaseError> {
+        let mut tables = shakmaty_syzygy::Tablebase::new();
+        let mut file_count = 0;
+        for dir in std::env::split_paths(path) {
+            file_count += tables.add_directory(&dir).map_err(|e| TablebaseError::Io(format!("{}: {}", dir.display(), e)))?;
+        }
+        if file_count == 0 {
+            return Err(TablebaseError::NoTables(path.to_string()));
+        }
+
+        let max_pieces = tables.max_pieces() as u32;
+        Ok(Tablebases { tables, max_pieces, file_count })
+    }
+
+    /// Whether `board` is small enough for the tables and has no castling rights (Syzygy has none).
+    pub fn covers(&self, board: &Board) -> bool {
+        board.occupied_bb.popcount() <= self.max_pieces && board.castling_rights == CastlingRights::NONE
+    }
+
+    /// Win/draw/loss for the side to move (-2 = loss, -1 = blessed loss, 0 = draw, 1 = cursed win, 2 = win),
+    /// assuming the position was reached by a capture or pawn move. Only needs the WDL tables.
+    pub fn probe_wdl(&self, board: &Board) -> Option<i32> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let pos = to_position(board)?;
+            self.tables.probe_wdl_after_zeroing(&pos).ok().map(i32::from)
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = board;
+            None
+        }
+    }
+
+    /// Distance to zeroing for the side to move: positive when winning, negative when losing, 0 for draws.
+    /// May be off by one ply for positions far from zeroing. Needs the DTZ tables.
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let pos = to_position(board)?;
+            if pos.is_checkmate() {
+                return Some(-1);
+            }
+            self.tables.probe_dtz(&pos).ok().map(|dtz| dtz.ignore_rounding().0)
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = board;
+            None
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn to_position(board: &Board) -> Option<Chess> {
+    let fen = Fen::from_ascii(board.to_fen().as_bytes()).ok()?;
+    fen.into_position(CastlingMode::Standard).ok()
+}
+
+
+// Shared by every search thread, each search keeps the tables it started with (see `Game::syzygy`)
+static SYZYGY: Lazy<RwLock<Option<Arc<Tablebases>>>> = Lazy::new(|| RwLock::new(None));
+
+pub fn syzygy_tablebases() -> Option<Arc<Tablebases>> {
+    SYZYGY.read().unwrap().clone()
+}
+
+pub fn set_syzygy_tablebases(tablebases: Option<Tablebases>) {
+    *SYZYGY.write().unwrap() = tablebases.map(Arc::new);
+}
+
+
+impl Game {
+    /// Picks up the currently loaded tables (when `use_tablebases` is set), called once at the start of every search.
+    pub fn syzygy_begin_search(&mut self) {
+        self.syzygy = if self.use_tablebases { syzygy_tablebases() } else { None };
+    }
+
+    /// Exact score from White's perspective for positions in the tables, probed right after captures
+    /// and pawn moves so that the 50-move counter is zero. Faster wins score higher, like mates.
+    pub fn probe_tablebase_wdl(&mut self, board: &Board, depth: u8) -> Option<i32> {
+        let tablebases = self.syzygy.as_ref()?;
+        if board.halfmove_clock != 0 || !tablebases.covers(board) {
+            return None;
+        }
+
+        let wdl = tablebases.probe_wdl(board)?;
+        self.tb_hits += 1;
+
+        let score = match wdl {
+            2 => TB_WIN_SCORE + depth as i32,
+            -2 => -TB_WIN_SCORE - depth as i32,
+            _ => 0, // cursed wins and blessed losses are draws under the 50-move rule
+        };
+        Some(if board.turn == Color::White { score } else { -score })
+    }
+
+    /// Keeps only the root moves with the best tablebase outcome, taking the 50-move counter into account.
+    /// Every move that still wins in time is kept so the search can choose between them.
+    /// Leaves `moves` untouched if the position is not covered or any probe fails.
+    pub fn filter_tablebase_root_moves(&mut self, moves: &mut Vec<ChessMove>) {
+        let Some(tablebases) = self.syzygy.clone() else {
+            return;
+        };
+        if !tablebases.covers(&self.board) {
+            return;
+        }
+
+        let mut ranks = Vec::with_capacity(moves.len());
+        for mv in moves.iter() {
+            let after = self.board.make_move(mv);
+            let Some(dtz) = tablebases.probe_dtz(&after) else {
+                return;
+            };
+            self.tb_hits += 1;
+
+            // `dtz` is from the opponent's point of view
+            let plies = dtz.abs() + after.halfmove_clock as i32;
+            let rank = match dtz {
+                d if d < 0 && plies <= 100 => 2,
+                d if d < 0 => 1,
+                0 => 0,
+                _ if plies <= 100 => -2,
+                _ => -1,
+            };
+            ranks.push(rank);
+        }
+
+        let best_rank = ranks.iter().copied().max().unwrap_or(0);
+        let mut ranks = ranks.into_iter();
+        moves.retain(|_| ranks.next() == Some(best_rank));
+    }
+}
@@ -0,0 +1,98 @@
+// Clock and time allocation for timed searches
+//
+// `now_ms` is a wall clock that also works in the browser, where `std::time::Instant` is not available.
+// The search compares it with `Game::deadline_ms` (see `should_stop`).
+
+use crate::board::*;
+
+/// Milliseconds since an arbitrary fixed point, only meaningful for differences.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> u64 {
+    use once_cell::sync::Lazy;
+    use std::time::Instant;
+
+    static START: Lazy<Instant> = Lazy::new(Instant::now);
+    START.elapsed().as_millis() as u64
+}
+
+/// Milliseconds since an arbitrary fixed point, only meaningful for differences.
+#[cfg(target_arch = "wasm32")]
+pub fn now_ms() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+/// Deadlines are stored in an `AtomicU64`, this one means "no deadline".
+pub const NO_DEADLINE: u64 = u64::MAX;
+
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+const MOVE_OVERHEAD_MS: u64 = 50; // kept in reserve for the GUI and the engine to exchange the move
+
+impl GoParams {
+    /// Whether the search is limited by the clock rather than by depth, including searches that only end on `stop`.
+    pub fn is_timed(&self) -> bool {
+        self.infinite || self.ponder || self.movetime.is_some() || self.wtime.is_some() || self.btime.is_some()
+    }
+
+    /// Thinking time for the side to move, `None` if the search is not limited by time.
+    /// For `go ponder` this is the time to use once the opponent plays the expected move.
+    pub fn time_budget_ms(&self, turn: Color) -> Option<u64> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(movetime) = self.movetime {
+            return Some(movetime.max(1));
+        }
+
+        let (time, inc) = match turn {
+            Color::White => (self.wtime?, self.winc.unwrap_or(0)),
+            Color::Black => (self.btime?, self.binc.unwrap_or(0)),
+        };
+        let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let budget = time / moves_to_go + inc * 3 / 4;
+        Some(budget.min(time.saturating_sub(MOVE_OVERHEAD_MS)).max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_the_clock_over_the_moves_to_go_plus_most_of_the_increment() {
+        let params = GoParams { wtime: Some(60_000), btime: Some(30_000), winc: Some(1000), binc: Some(2000), ..GoParams::default() };
+        assert_eq!(params.time_budget_ms(Color::White), Some(60_000 / 30 + 750));
+        assert_eq!(params.time_budget_ms(Color::Black), Some(30_000 / 30 + 1500));
+
+        let params = GoParams { wtime: Some(60_000), movestogo: Some(10), ..GoParams::default() };
+        assert_eq!(params.time_budget_ms(Color::White), Some(6000));
+        assert_eq!(params.time_budget_ms(Color::Black), None);
+    }
+
+    #[test]
+    fn keeps_the_move_overhead_and_at_least_a_millisecond() {
+        let params = GoParams { wtime: Some(1000), winc: Some(2000), movestogo: Some(1), ..GoParams::default() };
+        assert_eq!(params.time_budget_ms(Color::White), Some(1000 - MOVE_OVERHEAD_MS));
+
+        let params = GoParams { wtime: Some(20), movestogo: Some(0), ..GoParams::default() };
+        assert_eq!(params.time_budget_ms(Color::White), Some(1));
+    }
+
+    #[test]
+    fn movetime_and_infinite_override_the_clock() {
+        let params = GoParams { movetime: Some(500), wtime: Some(60_000), ..GoParams::default() };
+        assert_eq!(params.time_budget_ms(Color::White), Some(500));
+        assert_eq!(GoParams { movetime: Some(0), ..GoParams::default() }.time_budget_ms(Color::White), Some(1));
+
+        let params = GoParams { infinite: true, movetime: Some(500), ..GoParams::default() };
+        assert_eq!(params.time_budget_ms(Color::White), None);
+        assert!(params.is_timed());
+        assert!(!GoParams { depth: Some(5), ..GoParams::default() }.is_timed());
+    }
+
+    #[test]
+    fn ponder_searches_get_the_budget_for_after_ponderhit() {
+        let params = GoParams { ponder: true, btime: Some(30_000), ..GoParams::default() };
+        assert!(params.is_timed());
+        assert_eq!(params.time_budget_ms(Color::Black), Some(1000));
+    }
+}
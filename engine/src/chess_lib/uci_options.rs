@@ -0,0 +1,175 @@
+// UCI options of the engine
+//
+// Every option is described once in `UCI_OPTIONS`: the `uci` reply is generated from it and `setoption` values are
+// checked against it before `Engine` applies them. Names are matched case-insensitively, as the protocol asks.
+
+use crate::game::*;
+
+use std::fmt;
+
+pub const DEFAULT_MAX_DEPTH: u8 = 5;
+pub const DEFAULT_MAX_Q_DEPTH: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UciOptionType {
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    Combo { default: &'static str, values: &'static [&'static str] },
+    String { default: &'static str }, // "" is advertised as `<empty>`
+    Button,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: UciOptionType,
+}
+
+/// A `setoption` value that passed `UciOption::parse_value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciOptionValue {
+    Check(bool),
+    Spin(i64),
+    Combo(&'static str), // the spelling of `UCI_OPTIONS`
+    String(String), // `<empty>` is turned into ""
+    Button,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciOptionError {
+    UnknownOption(String),
+    InvalidValue { name: &'static str, value: String, expected: String },
+}
+
+impl fmt::Display for UciOptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciOptionError::UnknownOption(name) => write!(f, "Unknown option: '{}'", name),
+            UciOptionError::InvalidValue { name, value, expected } => write!(f, "Invalid value '{}' for option {} (expected {})", value, name, expected),
+        }
+    }
+}
+impl std::error::Error for UciOptionError {}
+
+pub const UCI_OPTIONS: &[UciOption] = &[
+    UciOption { name: "max_depth", kind: UciOptionType::Spin { default: DEFAULT_MAX_DEPTH as i64, min: 1, max: 20 } },
+    UciOption { name: "max_q_depth", kind: UciOptionType::Spin { default: DEFAULT_MAX_Q_DEPTH as i64, min: 1, max: 10 } },
+    UciOption { name: "is_evaluation_mode", kind: UciOptionType::Check { default: false } },
+    UciOption { name: "MultiPV", kind: UciOptionType::Spin { default: 1, min: 1, max: 16 } },
+    UciOption { name: "Ponder", kind: UciOptionType::Check { default: false } },
+    UciOption { name: "Hash", kind: UciOptionType::Spin { default: DEFAULT_HASH_MB as i64, min: 1, max: MAX_HASH_MB as i64 } },
+    UciOption { name: "Clear Hash", kind: UciOptionType::Button },
+    UciOption { name: "EvalFile", kind: UciOptionType::String { default: "" } },
+    UciOption { name: "UseNNUE", kind: UciOptionType::Check { default: false } },
+    UciOption { name: "NNUEFile", kind: UciOptionType::String { default: "" } },
+    UciOption { name: "SyzygyPath", kind: UciOptionType::String { default: "" } },
+    UciOption { name: "DTMPath", kind: UciOptionType::String { default: "" } },
+    UciOption { name: "OwnBook", kind: UciOptionType::Check { default: false } },
+    UciOption { name: "BookFile", kind: UciOptionType::String { default: "" } },
+    UciOption { name: "BookMoves", kind: UciOptionType::Combo { default: "Weighted", values: &["Weighted", "Best"] } },
+];
+
+pub fn find_uci_option(name: &str) -> Option<&'static UciOption> {
+    UCI_OPTIONS.iter().find(|option| option.name.eq_ignore_ascii_case(name))
+}
+
+impl UciOption {
+    /// The `option name ...` line of the `uci` reply.
+    pub fn uci_line(&self) -> String {
+        let kind = match self.kind {
+            UciOptionType::Check { default } => format!("check default {}", default),
+            UciOptionType::Spin { default, min, max } => format!("spin default {} min {} max {}", default, min, max),
+            UciOptionType::Combo { default, values } => {
+                let vars: Vec<String> = values.iter().map(|value| format!("var {}", value)).collect();
+                format!("combo default {} {}", default, vars.join(" "))
+            }
+            UciOptionType::String { default } => format!("string default {}", if default.is_empty() { "<empty>" } else { default }),
+            UciOptionType::Button => "button".to_string(),
+        };
+        format!("option name {} type {}", self.name, kind)
+    }
+
+    pub fn parse_value(&self, value: &str) -> Result<UciOptionValue, UciOptionError> {
+        let value = value.trim();
+        let invalid = |expected: String| UciOptionError::InvalidValue { name: self.name, value: value.to_string(), expected };
+        match self.kind {
+            UciOptionType::Check { .. } => match value.to_ascii_lowercase().as_str() {
+                "true" => Ok(UciOptionValue::Check(true)),
+                "false" => Ok(UciOptionValue::Check(false)),
+                _ => Err(invalid("true or false".to_string())),
+            },
+            UciOptionType::Spin { min, max, .. } => match value.parse::<i64>() {
+                Ok(number) if (min..=max).contains(&number) => Ok(UciOptionValue::Spin(number)),
+                _ => Err(invalid(format!("an integer from {} to {}", min, max))),
+            },
+            UciOptionType::Combo { values, .. } => values.iter()
+                .find(|v| v.eq_ignore_ascii_case(value))
+                .map(|&v| UciOptionValue::Combo(v))
+                .ok_or_else(|| invalid(format!("one of {}", values.join(", ")))),
+            UciOptionType::String { .. } => {
+                Ok(UciOptionValue::String(if value == "<empty>" { String::new() } else { value.to_string() }))
+            }
+            UciOptionType::Button => Ok(UciOptionValue::Button),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str, value: &str) -> Result<UciOptionValue, UciOptionError> {
+        find_uci_option(name).unwrap().parse_value(value)
+    }
+
+    #[test]
+    fn finds_options_case_insensitively() {
+        assert_eq!(find_uci_option("hash").map(|option| option.name), Some("Hash"));
+        assert_eq!(find_uci_option("CLEAR HASH").map(|option| option.name), Some("Clear Hash"));
+        assert_eq!(find_uci_option("Threads"), None);
+    }
+
+    #[test]
+    fn parses_values_of_every_type() {
+        assert_eq!(parse("Ponder", "TRUE"), Ok(UciOptionValue::Check(true)));
+        assert_eq!(parse("Ponder", " false "), Ok(UciOptionValue::Check(false)));
+        assert_eq!(parse("Hash", "64"), Ok(UciOptionValue::Spin(64)));
+        assert_eq!(parse("BookMoves", "best"), Ok(UciOptionValue::Combo("Best")));
+        assert_eq!(parse("SyzygyPath", "/tb/syzygy"), Ok(UciOptionValue::String("/tb/syzygy".to_string())));
+        assert_eq!(parse("SyzygyPath", "<empty>"), Ok(UciOptionValue::String(String::new())));
+        assert_eq!(parse("Clear Hash", ""), Ok(UciOptionValue::Button));
+    }
+
+    #[test]
+    fn rejects_values_out_of_range_or_of_the_wrong_type() {
+        let invalid = |name: &'static str, value: &str, expected: &str| {
+            Err(UciOptionError::InvalidValue { name, value: value.to_string(), expected: expected.to_string() })
+        };
+        assert_eq!(parse("Hash", "0"), invalid("Hash", "0", "an integer from 1 to 1024"));
+        assert_eq!(parse("Hash", "1025"), invalid("Hash", "1025", "an integer from 1 to 1024"));
+        assert_eq!(parse("MultiPV", "0"), invalid("MultiPV", "0", "an integer from 1 to 16"));
+        assert_eq!(parse("MultiPV", "two"), invalid("MultiPV", "two", "an integer from 1 to 16"));
+        assert_eq!(parse("Ponder", "yes"), invalid("Ponder", "yes", "true or false"));
+        assert_eq!(parse("BookMoves", "Random"), invalid("BookMoves", "Random", "one of Weighted, Best"));
+    }
+
+    #[test]
+    fn advertises_every_option_with_its_default_and_bounds() {
+        let line = |name: &str| find_uci_option(name).unwrap().uci_line();
+        assert_eq!(line("Hash"), "option name Hash type spin default 16 min 1 max 1024");
+        assert_eq!(line("Ponder"), "option name Ponder type check default false");
+        assert_eq!(line("BookMoves"), "option name BookMoves type combo default Weighted var Weighted var Best");
+        assert_eq!(line("EvalFile"), "option name EvalFile type string default <empty>");
+        assert_eq!(line("Clear Hash"), "option name Clear Hash type button");
+
+        for option in UCI_OPTIONS {
+            let default = match option.kind {
+                UciOptionType::Check { default } => default.to_string(),
+                UciOptionType::Spin { default, .. } => default.to_string(),
+                UciOptionType::Combo { default, .. } => default.to_string(),
+                UciOptionType::String { .. } | UciOptionType::Button => continue,
+            };
+            assert!(option.parse_value(&default).is_ok(), "{}", option.name);
+        }
+    }
+}
@@ -8,11 +8,39 @@ pub enum UciCommand {
     IsReady,
     NewGame,
     Position { fen: Option<Fen>, moves: Vec<String> }, // if fen is None, then command is 'position startpos ...'
-    Go { depth: Option<u8> }, // more can be added
+    Go(GoParams),
     Stop,
+    PonderHit,
     Quit,
+    Bench { depth: Option<u8> }, // not UCI, searches the built-in bench positions (native only)
+    Debug(bool),
+    Register, // the engine needs no registration, `register later` and `register name ... code ...` are accepted
+    Display, // not UCI, `d` prints the board, FEN, key, checkers and legal moves
+    Flip, // not UCI, mirrors the position (see `Board::mirrored`)
 }
 
+/// Limits of a `go` command, times in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoParams {
+    pub depth: Option<u8>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u64>,
+    pub infinite: bool,
+    pub ponder: bool, // search on the opponent's time until `ponderhit` or `stop`
+    pub nodes: Option<u64>,
+    pub mate: Option<u8>, // look for a mate in this many moves
+    pub searchmoves: Vec<String>, // only search these root moves, all if empty
+}
+
+// Ends the move list of `searchmoves`
+const GO_KEYWORDS: [&str; 12] = [
+    "depth", "movetime", "wtime", "btime", "winc", "binc", "movestogo", "infinite", "ponder", "nodes", "mate", "searchmoves",
+];
+
 // https://www.chess.com/terms/fen-chess
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Fen {
@@ -37,6 +65,7 @@ pub enum FenParseError {
     InvalidHalfmoveClock(std::num::ParseIntError),
     InvalidFullmoveNumber(std::num::ParseIntError),
     MissingFenFields,
+    IllegalPosition(String), // parses, but can not be reached in a game (e.g. no king)
 }
 
 use std::fmt;
@@ -55,6 +84,7 @@ impl fmt::Display for FenParseError {
             FenParseError::InvalidHalfmoveClock(e) => write!(f, "Invalid halfmove clock value: {}", e),
             FenParseError::InvalidFullmoveNumber(e) => write!(f, "Invalid fullmove number value: {}", e),
             FenParseError::MissingFenFields => write!(f, "Missing fields in FEN string"),
+            FenParseError::IllegalPosition(reason) => write!(f, "Illegal position: {}", reason),
         }
     }
 }
@@ -141,6 +171,7 @@ pub enum UciParseError {
     MissingSetOptionName,
     MissingSetOptionValue,
     MissingFenFields,
+    InvalidDebugCommand,
 }
 
 use std::error::Error; // Import Error trait
@@ -157,6 +188,7 @@ impl fmt::Display for UciParseError {
             UciParseError::InvalidSetOptionFormat => write!(f, "Invalid format for 'setoption' command"),
             UciParseError::MissingSetOptionName => write!(f, "Missing 'name' keyword for 'setoption' command"),
             UciParseError::MissingSetOptionValue => write!(f, "Missing 'value' keyword for 'setoption' command"),
+            UciParseError::InvalidDebugCommand => write!(f, "Expected 'debug on' or 'debug off'"),
         }
     }
 }
@@ -177,20 +209,31 @@ pub fn parse_command(line: &str) -> Result<UciCommand, UciParseError> {
 
     match command.as_deref() {
         Some("go")          => {
-            if let Some(token) = parts.next() {
+            // Unknown tokens and values that do not parse are ignored
+            let mut params = GoParams::default();
+            let mut parts = parts.peekable();
+            while let Some(token) = parts.next() {
                 match token {
-                    "depth" => {
-                        if let Some(depth) = parts.next() {
-                            let depth = depth.parse().unwrap_or(0);
-                            if depth != 0 {
-                                return Ok(UciCommand::Go{ depth: Some(depth) });
-                            }
+                    "depth"     => params.depth = parts.next().and_then(|v| v.parse().ok()).filter(|&d| d != 0),
+                    "movetime"  => params.movetime = parts.next().and_then(|v| v.parse().ok()),
+                    "wtime"     => params.wtime = parts.next().and_then(|v| v.parse::<i64>().ok()).map(|t| t.max(0) as u64),
+                    "btime"     => params.btime = parts.next().and_then(|v| v.parse::<i64>().ok()).map(|t| t.max(0) as u64),
+                    "winc"      => params.winc = parts.next().and_then(|v| v.parse().ok()),
+                    "binc"      => params.binc = parts.next().and_then(|v| v.parse().ok()),
+                    "movestogo" => params.movestogo = parts.next().and_then(|v| v.parse().ok()),
+                    "infinite"  => params.infinite = true,
+                    "ponder"    => params.ponder = true,
+                    "nodes"     => params.nodes = parts.next().and_then(|v| v.parse().ok()),
+                    "mate"      => params.mate = parts.next().and_then(|v| v.parse().ok()).filter(|&m| m != 0),
+                    "searchmoves" => {
+                        while let Some(mv) = parts.next_if(|t| !GO_KEYWORDS.contains(t)) {
+                            params.searchmoves.push(mv.to_string());
                         }
                     }
                     _ => {}
                 }
             }
-            return Ok(UciCommand::Go{ depth: None });
+            Ok(UciCommand::Go(params))
         },
 
         Some("position")    => {
@@ -241,7 +284,17 @@ pub fn parse_command(line: &str) -> Result<UciCommand, UciParseError> {
         Some("isready")     => Ok(UciCommand::IsReady),
         Some("ucinewgame")  => Ok(UciCommand::NewGame),
         Some("stop")        => Ok(UciCommand::Stop),
+        Some("ponderhit")   => Ok(UciCommand::PonderHit),
         Some("quit")        => Ok(UciCommand::Quit),
+        Some("bench")       => Ok(UciCommand::Bench { depth: parts.next().and_then(|d| d.parse().ok()).filter(|&d| d != 0) }),
+        Some("register")    => Ok(UciCommand::Register),
+        Some("d")           => Ok(UciCommand::Display),
+        Some("flip")        => Ok(UciCommand::Flip),
+        Some("debug")       => match parts.next() {
+            Some("on")  => Ok(UciCommand::Debug(true)),
+            Some("off") => Ok(UciCommand::Debug(false)),
+            _           => Err(UciParseError::InvalidDebugCommand),
+        },
 
         Some("setoption")   => {
             // setoption name <name> [value <val>]
@@ -310,4 +363,46 @@ pub fn parse_command(line: &str) -> Result<UciCommand, UciParseError> {
         Some(other)         => Err(UciParseError::UnknownCommand(other.to_string())),
         None                => Err(UciParseError::MissingCommand),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn go(line: &str) -> GoParams {
+        match parse_command(line).unwrap() {
+            UciCommand::Go(params) => params,
+            other => panic!("{} parsed as {:?}", line, other),
+        }
+    }
+
+    #[test]
+    fn parses_go_limits() {
+        assert_eq!(go("go nodes 5000"), GoParams { nodes: Some(5000), ..GoParams::default() });
+        assert_eq!(go("go movetime 250"), GoParams { movetime: Some(250), ..GoParams::default() });
+        assert_eq!(go("go mate 3"), GoParams { mate: Some(3), ..GoParams::default() });
+        assert_eq!(go("go depth 6 nodes 100"), GoParams { depth: Some(6), nodes: Some(100), ..GoParams::default() });
+        assert_eq!(go("go wtime 1000 btime -50 winc 10 binc 20 movestogo 5"), GoParams {
+            wtime: Some(1000), btime: Some(0), winc: Some(10), binc: Some(20), movestogo: Some(5), ..GoParams::default()
+        });
+    }
+
+    #[test]
+    fn ignores_zero_and_malformed_go_values() {
+        assert_eq!(go("go mate 0"), GoParams::default());
+        assert_eq!(go("go depth 0"), GoParams::default());
+        assert_eq!(go("go nodes many movetime"), GoParams::default());
+        assert_eq!(go("go mate 300"), GoParams::default());
+        assert_eq!(go("go sideways infinite"), GoParams { infinite: true, ..GoParams::default() });
+    }
+
+    #[test]
+    fn searchmoves_end_at_the_next_keyword() {
+        let moves = |list: &[&str]| list.iter().map(|mv| mv.to_string()).collect::<Vec<_>>();
+        assert_eq!(go("go searchmoves e2e4 d2d4"), GoParams { searchmoves: moves(&["e2e4", "d2d4"]), ..GoParams::default() });
+        assert_eq!(go("go searchmoves g1f3 movetime 100 ponder"), GoParams {
+            searchmoves: moves(&["g1f3"]), movetime: Some(100), ponder: true, ..GoParams::default()
+        });
+        assert_eq!(go("go infinite searchmoves"), GoParams { infinite: true, ..GoParams::default() });
+    }
+}
@@ -0,0 +1,208 @@
+// https://www.gnu.org/software/xboard/engine-intf.html
+//
+// Commands of the xboard / WinBoard protocol (CECP) understood by the engine. `native_main` switches to this
+// parser when the first command is `xboard`; `Engine::process_xboard_command` maps them onto the same searches
+// as UCI.
+
+use crate::board::*;
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XboardCommand {
+    Xboard,
+    Protover(u32),
+    New,
+    UserMove(String), // `usermove e2e4`, or a bare move for GUIs that ignore the `usermove` feature
+    Go,
+    Force,
+    Level { moves_per_session: u32, base_ms: u64, increment_ms: u64 }, // 0 moves per session: the whole game
+    St { move_time_ms: u64 },
+    Sd { depth: u8 },
+    Time { engine_ms: u64 }, // sent in centiseconds
+    Otim { opponent_ms: u64 },
+    Undo,
+    Remove, // takes back two moves, so the user can try another one
+    SetBoard(Fen),
+    Post,
+    NoPost,
+    Result(String), // e.g. `1-0 {White mates}`
+    Ping(u32),
+    MoveNow, // `?`
+    Quit,
+    Ignored, // commands without meaning for this engine, like `random`, `hard` or `accepted`
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XboardParseError {
+    UnknownCommand(String),
+    MissingCommand,
+    MissingArgument(&'static str),
+    InvalidArgument { command: &'static str, value: String },
+    FenParseError(FenParseError),
+}
+
+impl fmt::Display for XboardParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XboardParseError::UnknownCommand(cmd) => write!(f, "Unknown command: '{}'", cmd),
+            XboardParseError::MissingCommand => write!(f, "No command provided"),
+            XboardParseError::MissingArgument(cmd) => write!(f, "Missing argument for '{}'", cmd),
+            XboardParseError::InvalidArgument { command, value } => write!(f, "Invalid argument '{}' for '{}'", value, command),
+            XboardParseError::FenParseError(e) => write!(f, "FEN parsing error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for XboardParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XboardParseError::FenParseError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+const IGNORED_COMMANDS: [&str; 12] = [
+    "accepted", "rejected", "random", "hard", "easy", "computer", "name", "rating", "ics", "variant", "draw", "nopause",
+];
+
+/// Moves in coordinate notation (`e2e4`, `e7e8q`), which xboard sends without `usermove` in protocol 1.
+fn is_coordinate_move(token: &str) -> bool {
+    let bytes = token.as_bytes();
+    let square = |file: u8, rank: u8| (b'a'..=b'h').contains(&file) && (b'1'..=b'8').contains(&rank);
+    match bytes.len() {
+        4 => square(bytes[0], bytes[1]) && square(bytes[2], bytes[3]),
+        5 => square(bytes[0], bytes[1]) && square(bytes[2], bytes[3]) && b"qrbn".contains(&bytes[4]),
+        _ => false,
+    }
+}
+
+/// Seconds with an optional fraction (`level` increments and `st` may be fractional in protocol 2).
+fn parse_seconds_ms(command: &'static str, value: &str) -> Result<u64, XboardParseError> {
+    value.parse::<f64>().ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(|seconds| (seconds * 1000.0) as u64)
+        .ok_or_else(|| XboardParseError::InvalidArgument { command, value: value.to_string() })
+}
+
+fn parse_argument<T: std::str::FromStr>(command: &'static str, value: Option<&str>) -> Result<T, XboardParseError> {
+    let value = value.ok_or(XboardParseError::MissingArgument(command))?;
+    value.parse().map_err(|_| XboardParseError::InvalidArgument { command, value: value.to_string() })
+}
+
+pub fn parse_xboard_command(line: &str) -> Result<XboardCommand, XboardParseError> {
+    let mut parts = line.trim().split_ascii_whitespace();
+    let Some(command) = parts.next() else {
+        return Err(XboardParseError::MissingCommand);
+    };
+
+    match command {
+        "xboard"    => Ok(XboardCommand::Xboard),
+        "protover"  => Ok(XboardCommand::Protover(parse_argument("protover", parts.next())?)),
+        "new"       => Ok(XboardCommand::New),
+        "usermove"  => Ok(XboardCommand::UserMove(parse_argument("usermove", parts.next())?)),
+        "go"        => Ok(XboardCommand::Go),
+        "force"     => Ok(XboardCommand::Force),
+        "level"     => {
+            // level MPS BASE INC, BASE is minutes or minutes:seconds
+            let moves_per_session = parse_argument("level", parts.next())?;
+            let base = parts.next().ok_or(XboardParseError::MissingArgument("level"))?;
+            let invalid_base = || XboardParseError::InvalidArgument { command: "level", value: base.to_string() };
+            let (minutes, seconds) = base.split_once(':').unwrap_or((base, "0"));
+            let minutes: u64 = minutes.parse().map_err(|_| invalid_base())?;
+            let seconds: u64 = seconds.parse().map_err(|_| invalid_base())?;
+            let increment = parts.next().ok_or(XboardParseError::MissingArgument("level"))?;
+            Ok(XboardCommand::Level {
+                moves_per_session,
+                base_ms: (minutes * 60 + seconds) * 1000,
+                increment_ms: parse_seconds_ms("level", increment)?,
+            })
+        }
+        "st"        => {
+            let seconds = parts.next().ok_or(XboardParseError::MissingArgument("st"))?;
+            Ok(XboardCommand::St { move_time_ms: parse_seconds_ms("st", seconds)? })
+        }
+        "sd"        => {
+            let depth: u8 = parse_argument("sd", parts.next())?;
+            Ok(XboardCommand::Sd { depth: depth.max(1) })
+        }
+        "time"      => Ok(XboardCommand::Time { engine_ms: parse_argument::<i64>("time", parts.next())?.max(0) as u64 * 10 }),
+        "otim"      => Ok(XboardCommand::Otim { opponent_ms: parse_argument::<i64>("otim", parts.next())?.max(0) as u64 * 10 }),
+        "undo"      => Ok(XboardCommand::Undo),
+        "remove"    => Ok(XboardCommand::Remove),
+        "setboard"  => {
+            let fen = parts.collect::<Vec<_>>().join(" ");
+            Ok(XboardCommand::SetBoard(fen.parse::<Fen>().map_err(XboardParseError::FenParseError)?))
+        }
+        "post"      => Ok(XboardCommand::Post),
+        "nopost"    => Ok(XboardCommand::NoPost),
+        "result"    => Ok(XboardCommand::Result(parts.collect::<Vec<_>>().join(" "))),
+        "ping"      => Ok(XboardCommand::Ping(parse_argument("ping", parts.next())?)),
+        "?"         => Ok(XboardCommand::MoveNow),
+        "quit"      => Ok(XboardCommand::Quit),
+        ignored if IGNORED_COMMANDS.contains(&ignored) => Ok(XboardCommand::Ignored),
+        mv if is_coordinate_move(mv) => Ok(XboardCommand::UserMove(mv.to_string())),
+        other       => Err(XboardParseError::UnknownCommand(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> XboardCommand {
+        parse_xboard_command(line).unwrap()
+    }
+
+    #[test]
+    fn parses_commands_without_arguments() {
+        assert_eq!(parse("xboard"), XboardCommand::Xboard);
+        assert_eq!(parse("  new \n"), XboardCommand::New);
+        assert_eq!(parse("force"), XboardCommand::Force);
+        assert_eq!(parse("?"), XboardCommand::MoveNow);
+        assert_eq!(parse("random"), XboardCommand::Ignored);
+        assert_eq!(parse("accepted usermove"), XboardCommand::Ignored);
+    }
+
+    #[test]
+    fn parses_moves_with_and_without_usermove() {
+        assert_eq!(parse("usermove e2e4"), XboardCommand::UserMove("e2e4".to_string()));
+        assert_eq!(parse("e7e8q"), XboardCommand::UserMove("e7e8q".to_string()));
+        assert_eq!(parse_xboard_command("e7e8k"), Err(XboardParseError::UnknownCommand("e7e8k".to_string())));
+        assert_eq!(parse_xboard_command("i2i4"), Err(XboardParseError::UnknownCommand("i2i4".to_string())));
+        assert_eq!(parse_xboard_command("usermove"), Err(XboardParseError::MissingArgument("usermove")));
+    }
+
+    #[test]
+    fn parses_time_controls() {
+        assert_eq!(parse("level 40 5 0"), XboardCommand::Level { moves_per_session: 40, base_ms: 300_000, increment_ms: 0 });
+        assert_eq!(parse("level 0 2:30 1.5"), XboardCommand::Level { moves_per_session: 0, base_ms: 150_000, increment_ms: 1500 });
+        assert_eq!(parse("st 0.5"), XboardCommand::St { move_time_ms: 500 });
+        assert_eq!(parse("sd 0"), XboardCommand::Sd { depth: 1 });
+        assert_eq!(parse("time 6000"), XboardCommand::Time { engine_ms: 60_000 });
+        assert_eq!(parse("otim -20"), XboardCommand::Otim { opponent_ms: 0 });
+
+        assert_eq!(parse_xboard_command("level 40 5"), Err(XboardParseError::MissingArgument("level")));
+        assert_eq!(parse_xboard_command("level 40 5:x 0"),
+                   Err(XboardParseError::InvalidArgument { command: "level", value: "5:x".to_string() }));
+        assert_eq!(parse_xboard_command("st -1"), Err(XboardParseError::InvalidArgument { command: "st", value: "-1".to_string() }));
+        assert_eq!(parse_xboard_command("sd deep"), Err(XboardParseError::InvalidArgument { command: "sd", value: "deep".to_string() }));
+    }
+
+    #[test]
+    fn parses_setboard_result_and_ping() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(parse(&format!("setboard {}", fen)), XboardCommand::SetBoard(fen.parse().unwrap()));
+        assert!(matches!(parse_xboard_command("setboard 8/8 w"), Err(XboardParseError::FenParseError(_))));
+        assert_eq!(parse("result 1-0 {White mates}"), XboardCommand::Result("1-0 {White mates}".to_string()));
+        assert_eq!(parse("ping 7"), XboardCommand::Ping(7));
+        assert_eq!(parse("protover 2"), XboardCommand::Protover(2));
+    }
+
+    #[test]
+    fn rejects_unknown_and_empty_lines() {
+        assert_eq!(parse_xboard_command("   "), Err(XboardParseError::MissingCommand));
+        assert_eq!(parse_xboard_command("analyze"), Err(XboardParseError::UnknownCommand("analyze".to_string())));
+    }
+}
@@ -0,0 +1,387 @@
+// Self-play training data generator.
+//
+// Plays games from randomized openings with a fixed node budget per move and writes every
+// quiet position together with its search score and the final game result.
+//
+// usage: datagen [--games N] [--threads N] [--nodes N] [--depth N] [--random-plies N]
+//                [--max-plies N] [--seed N] [--format binary|text] [--out PATH]
+//                [--keep-checks] [--keep-tactical]
+//
+// Binary format: fixed 32 byte little-endian records
+//   u64    occupancy bitboard (a1 = bit 0)
+//   [u8;16] one nibble per occupied square in occupancy order (low nibble first):
+//          piece type (0 = pawn .. 5 = king) | 8 if black
+//   u8     side to move (0 = white, 1 = black)
+//   u8     castling rights (K = 1, Q = 2, k = 4, q = 8)
+//   u8     en passant square (0-63) or 255
+//   u8     halfmove clock
+//   i16    search score in centipawns from White's perspective
+//   u8     game result from White's perspective (0 = loss, 1 = draw, 2 = win)
+//   u8     reserved (0)
+//
+// Text format: one "<fen> | <score> | <result>" line per position, result being 1.0, 0.5 or 0.0 for White.
+//
+// Scores are written as the search returns them, in centipawns (a pawn is worth 100). Games that have not
+// ended after --max-plies are dropped, their result is unknown.
+
+#[allow(dead_code, unused_imports)] // the tool only uses part of the engine
+mod chess_lib;
+use chess_lib::*;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam::channel::unbounded;
+
+const RECORD_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Binary,
+    Text,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    games: usize,
+    threads: usize,
+    nodes: u64,
+    depth: u8,
+    random_plies: usize,
+    max_plies: usize,
+    seed: u64,
+    format: OutputFormat,
+    out: String,
+    keep_checks: bool,
+    keep_tactical: bool,
+}
+
+impl Config {
+    fn from_args() -> Result<Self, String> {
+        let mut config = Config {
+            games: 100,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            nodes: 5000,
+            depth: 10,
+            random_plies: 8,
+            max_plies: 400,
+            seed: rand::rng().random(),
+            format: OutputFormat::Binary,
+            out: String::new(),
+            keep_checks: false,
+            keep_tactical: false,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {}", name));
+            match arg.as_str() {
+                "--games"        => config.games = parse_arg(&arg, &value(&arg)?)?,
+                "--threads"      => config.threads = parse_arg(&arg, &value(&arg)?)?,
+                "--nodes"        => config.nodes = parse_arg(&arg, &value(&arg)?)?,
+                "--depth"        => config.depth = parse_arg(&arg, &value(&arg)?)?,
+                "--random-plies" => config.random_plies = parse_arg(&arg, &value(&arg)?)?,
+                "--max-plies"    => config.max_plies = parse_arg(&arg, &value(&arg)?)?,
+                "--seed"         => config.seed = parse_arg(&arg, &value(&arg)?)?,
+                "--out"          => config.out = value(&arg)?,
+                "--format"       => config.format = match value(&arg)?.as_str() {
+                    "binary" => OutputFormat::Binary,
+                    "text" => OutputFormat::Text,
+                    other => return Err(format!("unknown format '{}' (expected binary or text)", other)),
+                },
+                "--keep-checks"   => config.keep_checks = true,
+                "--keep-tactical" => config.keep_tactical = true,
+                other => return Err(format!("unknown argument '{}'", other)),
+            }
+        }
+
+        if config.threads == 0 || config.depth == 0 || config.nodes == 0 {
+            return Err("--threads, --depth and --nodes must be at least 1".to_string());
+        }
+        if config.out.is_empty() {
+            config.out = match config.format {
+                OutputFormat::Binary => "datagen.bin".to_string(),
+                OutputFormat::Text => "datagen.txt".to_string(),
+            };
+        }
+        Ok(config)
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, name))
+}
+
+
+struct Sample {
+    board: Board,
+    score: i32,
+}
+
+/// Result from White's perspective: 0 = loss, 1 = draw, 2 = win. `None` while the game is still going on.
+fn result_for_white(game_state: &GameState) -> Option<u8> {
+    match game_state {
+        GameState::Checkmate(Color::White) => Some(0),
+        GameState::Checkmate(Color::Black) => Some(2),
+        GameState::Ongoing => None,
+        _ => Some(1),
+    }
+}
+
+fn encode_binary(sample: &Sample, result: u8, out: &mut Vec<u8>) {
+    let board = &sample.board;
+    let start = out.len();
+
+    out.extend_from_slice(&board.occupied_bb.as_u64().to_le_bytes());
+
+    let mut pieces = [0u8; 16];
+    for (i, sq) in board.occupied_bb.iter().enumerate() {
+        let (piece_type, color) = board.piece_on_square(sq).unwrap();
+        let nibble = piece_type as u8 | ((color as u8) << 3);
+        pieces[i / 2] |= nibble << ((i % 2) * 4);
+    }
+    out.extend_from_slice(&pieces);
+
+    let mut castling = 0u8;
+    for (bit, right) in [CastlingRights::WHITE_KINGSIDE, CastlingRights::WHITE_QUEENSIDE,
+                         CastlingRights::BLACK_KINGSIDE, CastlingRights::BLACK_QUEENSIDE].iter().enumerate() {
+        if board.castling_rights.has_right(*right) {
+            castling |= 1 << bit;
+        }
+    }
+
+    out.push(board.turn as u8);
+    out.push(castling);
+    out.push(board.en_passant_square.map_or(255, |sq| sq.to_u8()));
+    out.push(board.halfmove_clock);
+    out.extend_from_slice(&(sample.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes());
+    out.push(result);
+    out.push(0);
+
+    debug_assert_eq!(out.len() - start, RECORD_SIZE);
+}
+
+fn encode_text(sample: &Sample, result: u8, out: &mut Vec<u8>) {
+    let result = match result {
+        2 => "1.0",
+        1 => "0.5",
+        _ => "0.0",
+    };
+    writeln!(out, "{} | {} | {}", sample.board.to_fen(), sample.score, result).unwrap();
+}
+
+
+/// Iterative deepening up to `config.depth` within the node budget.
+/// An iteration cut short by the budget is discarded in favour of the last complete one, unless none finished:
+/// then the root moves searched before the budget ran out decide.
+fn search_position(game: &mut Game, config: &Config) -> Option<(ChessMove, i32)> {
+    game.nodes = 0;
+    game.node_limit = Some(config.nodes);
+
+    let mut best = None;
+    for depth in 1..=config.depth {
+        let result = game.find_best_move_with_score(depth);
+        if game.should_stop() {
+            best = best.or(result);
+            break;
+        }
+        best = result;
+        if best.is_none() {
+            break;
+        }
+    }
+    best
+}
+
+/// Plays random moves from the start position. Returns `None` if the game ended during the opening.
+fn random_opening(rng: &mut StdRng, config: &Config, stop_signal: &Arc<AtomicBool>) -> Option<Game> {
+    let mut game = Game::new(Board::new_start_pos(), config.depth, 3, Arc::clone(stop_signal));
+    let mut pseudo_legal_moves = Vec::with_capacity(200);
+    let mut legal_moves = Vec::with_capacity(100);
+
+    for _ in 0..config.random_plies {
+        game.board.generate_legal_moves(&mut pseudo_legal_moves, &mut legal_moves);
+        if legal_moves.is_empty() {
+            return None;
+        }
+        let mv = legal_moves[rng.random_range(0..legal_moves.len())];
+        game.make_move(&mv);
+    }
+
+    if game.get_game_state() != GameState::Ongoing {
+        return None;
+    }
+    Some(game)
+}
+
+fn play_game(rng: &mut StdRng, config: &Config, stop_signal: &Arc<AtomicBool>) -> Vec<u8> {
+    let mut game = loop {
+        if let Some(game) = random_opening(rng, config, stop_signal) {
+            break game;
+        }
+    };
+
+    let mut samples = Vec::new();
+
+    for _ in 0..config.max_plies {
+        if game.get_game_state() != GameState::Ongoing {
+            break;
+        }
+
+        let Some((mv, score)) = search_position(&mut game, config) else {
+            // No move although the game goes on: the result is unknown, labelling the samples a draw would be wrong
+            return Vec::new();
+        };
+
+        // Tablebase wins (syzygy.rs, dtm.rs) are decisive like mates, which score even higher
+        let is_tactical = mv.is_capture() || mv.promotion().is_some() || score.abs() >= TB_WIN_SCORE;
+        if (config.keep_checks || !game.board.is_check()) && (config.keep_tactical || !is_tactical) {
+            samples.push(Sample { board: game.board.clone(), score });
+        }
+
+        game.make_move(&mv);
+    }
+
+    // Checked after the last move too, a mate on the final ply is still a win.
+    // A game still going on after `max_plies` has no result: calling it a draw would mislabel won positions
+    let Some(result) = result_for_white(&game.get_game_state()) else {
+        return Vec::new();
+    };
+
+    let mut out = Vec::with_capacity(samples.len() * RECORD_SIZE);
+    for sample in &samples {
+        match config.format {
+            OutputFormat::Binary => encode_binary(sample, result, &mut out),
+            OutputFormat::Text => encode_text(sample, result, &mut out),
+        }
+    }
+    out
+}
+
+
+fn main() {
+    let config = match Config::from_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("datagen: {}", e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("datagen: {:?}", config);
+
+    let file = File::create(&config.out).unwrap_or_else(|e| {
+        eprintln!("datagen: could not create {}: {}", config.out, e);
+        std::process::exit(1);
+    });
+    let mut writer = BufWriter::new(file);
+
+    init_kpk_bitbase(); // generate it before the workers start searching
+
+    let (sender, receiver) = unbounded::<Vec<u8>>();
+    let games_started = Arc::new(AtomicUsize::new(0));
+
+    let mut handles = Vec::new();
+    for thread_idx in 0..config.threads {
+        let sender = sender.clone();
+        let config = config.clone();
+        let games_started = Arc::clone(&games_started);
+        handles.push(thread::spawn(move || {
+            let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(thread_idx as u64));
+            let stop_signal = Arc::new(AtomicBool::new(false)); // never set, the node budget ends each search
+            while games_started.fetch_add(1, Ordering::Relaxed) < config.games {
+                let data = play_game(&mut rng, &config, &stop_signal);
+                if sender.send(data).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+    drop(sender);
+
+    let mut games_done = 0;
+    let mut bytes_written = 0;
+    let mut positions = 0;
+    for data in receiver {
+        writer.write_all(&data).expect("Failed to write training data");
+        games_done += 1;
+        bytes_written += data.len();
+        positions += match config.format {
+            OutputFormat::Binary => data.len() / RECORD_SIZE,
+            OutputFormat::Text => data.iter().filter(|&&b| b == b'\n').count(),
+        };
+        if games_done % 10 == 0 || games_done == config.games {
+            eprintln!("datagen: {}/{} games, {} positions, {} bytes", games_done, config.games, positions, bytes_written);
+        }
+    }
+
+    for handle in handles {
+        handle.join().expect("datagen worker panicked");
+    }
+    writer.flush().expect("Failed to write training data");
+    eprintln!("datagen: wrote {} positions from {} games to {}", positions, games_done, config.out);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(fen: &str, score: i32) -> Sample {
+        Sample { board: Board::from_fen(&fen.parse::<Fen>().unwrap()).unwrap(), score }
+    }
+
+    fn config(max_plies: usize) -> Config {
+        Config {
+            games: 1, threads: 1, nodes: 300, depth: 2, random_plies: 4, max_plies, seed: 7,
+            format: OutputFormat::Binary, out: String::new(), keep_checks: true, keep_tactical: true,
+        }
+    }
+
+    #[test]
+    fn encodes_binary_records() {
+        let mut out = Vec::new();
+        encode_binary(&sample("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", -123), 2, &mut out);
+        let mut expected = 0xFFFF_0000_0000_FFFFu64.to_le_bytes().to_vec();
+        expected.extend_from_slice(&[0x13, 0x42, 0x25, 0x31, 0x00, 0x00, 0x00, 0x00,
+                                     0x88, 0x88, 0x88, 0x88, 0x9B, 0xCA, 0xAD, 0xB9]);
+        expected.extend_from_slice(&[0, 15, 255, 0]);
+        expected.extend_from_slice(&(-123i16).to_le_bytes());
+        expected.extend_from_slice(&[2, 0]);
+        assert_eq!(out, expected);
+
+        // Black to move with an en passant square, the score is clamped to the i16 range
+        out.clear();
+        encode_binary(&sample("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1", 50_000), 0, &mut out);
+        assert_eq!(out.len(), RECORD_SIZE);
+        assert_eq!(out[..8], ((1u64 << 4) | (1 << 27) | (1 << 28) | (1 << 60)).to_le_bytes());
+        assert_eq!(out[8..10], [0x85, 0xD0]);
+        assert_eq!(out[24..], [1, 0, 20, 0, 0xFF, 0x7F, 0, 0]);
+    }
+
+    #[test]
+    fn encodes_text_lines() {
+        let mut out = Vec::new();
+        encode_text(&sample("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1", -57), 1, &mut out);
+        encode_text(&sample("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1", 10), 2, &mut out);
+        assert_eq!(String::from_utf8(out).unwrap(), "4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1 | -57 | 0.5\n\
+                                                     4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1 | 10 | 1.0\n");
+    }
+
+    #[test]
+    fn only_finished_games_have_a_result() {
+        assert_eq!(result_for_white(&GameState::Checkmate(Color::Black)), Some(2));
+        assert_eq!(result_for_white(&GameState::Checkmate(Color::White)), Some(0));
+        assert_eq!(result_for_white(&GameState::ThreefoldRepetitionDraw), Some(1));
+        assert_eq!(result_for_white(&GameState::Ongoing), None);
+
+        // Two plies are not enough to finish a game, its positions are dropped instead of being labelled draws
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        assert!(play_game(&mut StdRng::seed_from_u64(1), &config(2), &stop_signal).is_empty());
+    }
+}
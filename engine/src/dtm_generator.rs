@@ -0,0 +1,356 @@
+// Distance-to-mate table generation for tbgen, see src/chess_lib/dtm.rs for the indexing and the file format.
+
+use crate::board::*;
+use crate::dtm::*;
+
+use std::fmt;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerateError {
+    Dtm(DtmError),
+    TooDeep(String),
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::Dtm(e) => write!(f, "{}", e),
+            GenerateError::TooDeep(m) => write!(f, "DTM table {} has mates longer than {} plies", m, DTM_MAX_PLIES),
+        }
+    }
+}
+
+impl From<DtmError> for GenerateError {
+    fn from(e: DtmError) -> Self {
+        GenerateError::Dtm(e)
+    }
+}
+
+
+/// Generates `layout` and every table it depends on that is not in `tables` yet.
+/// `progress` is told about every table as it is finished.
+pub fn generate(tables: &mut DtmTables, layout: &DtmLayout, progress: &mut dyn FnMut(&DtmTable)) -> Result<(), GenerateError> {
+    if tables.tables.contains_key(&layout.signature) {
+        return Ok(());
+    }
+    for subtable in subtables(layout) {
+        generate(tables, &subtable, progress)?;
+    }
+
+    let table = generate_table(layout, tables)?;
+    progress(&table);
+    tables.insert(table);
+    Ok(())
+}
+
+/// Longest distance to mate in the table, in plies.
+pub fn longest_mate(table: &DtmTable) -> u8 {
+    table.values.iter().map(|&value| value.saturating_sub(1)).max().unwrap_or(0)
+}
+
+/// Run-length encoded file contents, read back by `DtmTable::from_bytes`.
+pub fn table_to_bytes(table: &DtmTable) -> Vec<u8> {
+    let name = table.layout.name();
+    let mut out = Vec::new();
+    out.extend_from_slice(DTM_MAGIC);
+    out.extend_from_slice(&DTM_VERSION.to_le_bytes());
+    out.push(name.len() as u8);
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(&(table.values.len() as u32).to_le_bytes());
+
+    let values = &table.values;
+    let run_at = |i: usize| values[i..].iter().take(129).take_while(|&&v| v == values[i]).count();
+
+    let mut i = 0;
+    while i < values.len() {
+        let run = run_at(i);
+        if run >= 2 {
+            out.push((run + 126) as u8);
+            out.push(values[i]);
+            i += run;
+            continue;
+        }
+
+        let start = i;
+        while i < values.len() && i - start < 128 && run_at(i) < 2 {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        out.extend_from_slice(&values[start..i]);
+    }
+    out
+}
+
+/// Tables reached by a capture, kings-only material excluded.
+fn subtables(layout: &DtmLayout) -> Vec<DtmLayout> {
+    let mut subtables: Vec<DtmLayout> = Vec::new();
+    for &(piece_type, color) in &layout.pieces[2..] {
+        let mut signature = layout.signature;
+        signature.counts[color as usize][piece_type as usize] -= 1;
+        let layout = DtmLayout::from_signature(&canonical_signature(&signature).0);
+        if layout.pieces.len() > 2 && !subtables.contains(&layout) {
+            subtables.push(layout);
+        }
+    }
+    subtables
+}
+
+fn decode(layout: &DtmLayout, mut index: usize) -> (bool, [u8; DTM_MAX_PIECES]) {
+    let mut squares = [0u8; DTM_MAX_PIECES];
+    for slot in (1..layout.pieces.len()).rev() {
+        squares[slot] = (index % 64) as u8;
+        index /= 64;
+    }
+    squares[0] = TRIANGLE_SQUARES[index % TRIANGLE_SQUARES.len()];
+    (index < TRIANGLE_SQUARES.len(), squares)
+}
+
+fn board_from_squares(layout: &DtmLayout, white_to_move: bool, squares: &[u8]) -> Board {
+    let mut board = Board {
+        piece_bbs: [Bitboard::EMPTY; 6],
+        color_bbs: [Bitboard::EMPTY; 2],
+        occupied_bb: Bitboard::EMPTY,
+        turn: if white_to_move { Color::White } else { Color::Black },
+        castling_rights: CastlingRights::NONE,
+        en_passant_square: None,
+        halfmove_clock: 0,
+        fullmove_number: 1,
+    };
+    for (&(piece_type, color), &sq) in layout.pieces.iter().zip(squares) {
+        board.piece_bbs[piece_type as usize].set(Square::from_u8(sq));
+        board.color_bbs[color as usize].set(Square::from_u8(sq));
+    }
+    board.update_occupied_bb();
+    board
+}
+
+/// Retrograde analysis of one table, all tables reached by captures must be in `subtables`.
+///
+/// Every position is first looked at once with `generate_legal_moves`: mates, stalemates and captures
+/// into the subtables are resolved, the other moves are counted. Then, one ply at a time, the predecessors of
+/// the positions resolved in the previous ply are found by moving pieces backwards: a predecessor of a lost
+/// position is won, a predecessor whose every move leads to a won position is lost. What is left is drawn.
+fn generate_table(layout: &DtmLayout, subtables: &DtmTables) -> Result<DtmTable, GenerateError> {
+    let size = layout.size();
+    let slots = layout.pieces.len();
+
+    let mut values = vec![DTM_DRAW; size];
+    let mut open_moves = vec![0u8; size];      // moves to different positions that are not known to lose yet
+    let mut capture_win = vec![DTM_DRAW; size];  // fastest win by a capture
+    let mut capture_loss = vec![DTM_DRAW; size]; // slowest loss by a capture
+    let mut has_draw = vec![false; size];        // a capture or stalemate that draws, so the position can't be lost
+    let mut scheduled: Vec<Vec<u32>> = vec![Vec::new(); DTM_MAX_PLIES + 1];
+    let mut frontier: Vec<u32> = Vec::new();
+
+    let mut pseudo_legal_moves = Vec::with_capacity(64);
+    let mut legal_moves = Vec::with_capacity(64);
+    let mut children = Vec::with_capacity(64);
+
+    for index in 0..size {
+        let (white_to_move, squares) = decode(layout, index);
+        let squares = &squares[..slots];
+
+        let mut canonical = [0u8; DTM_MAX_PIECES];
+        canonical[..slots].copy_from_slice(squares);
+        let occupied = squares.iter().fold(0u64, |acc, &sq| acc | 1 << sq);
+        if occupied.count_ones() as usize != slots || layout.index(white_to_move, &mut canonical[..slots]) != index {
+            values[index] = DTM_INVALID; // overlapping pieces, or a mirror image of another index
+            continue;
+        }
+
+        let board = board_from_squares(layout, white_to_move, squares);
+        let waiting = board.find_king_square(board.turn.opponent()).unwrap();
+        if board.is_square_attacked(waiting, board.turn) {
+            values[index] = DTM_INVALID;
+            continue;
+        }
+
+        board.generate_legal_moves(&mut pseudo_legal_moves, &mut legal_moves);
+        if legal_moves.is_empty() {
+            if board.is_check() {
+                values[index] = 1; // mated
+                frontier.push(index as u32);
+            } else {
+                has_draw[index] = true;
+            }
+            continue;
+        }
+
+        children.clear();
+        for mv in &legal_moves {
+            if mv.is_capture() {
+                let value = subtables.probe_value(&board.make_move(mv))?.unwrap();
+                match DtmResult::from_value(value) {
+                    DtmResult::Draw => has_draw[index] = true,
+                    DtmResult::Loss(plies) => {
+                        let win = plies + 2;
+                        if capture_win[index] == DTM_DRAW || win < capture_win[index] {
+                            capture_win[index] = win;
+                        }
+                    }
+                    DtmResult::Win(plies) => capture_loss[index] = capture_loss[index].max(plies + 2),
+                }
+            } else {
+                let mut child = [0u8; DTM_MAX_PIECES];
+                child[..slots].copy_from_slice(squares);
+                let slot = squares.iter().position(|&sq| sq == mv.from().to_u8()).unwrap();
+                child[slot] = mv.to().to_u8();
+                children.push(layout.index(!white_to_move, &mut child[..slots]));
+            }
+        }
+        children.sort_unstable();
+        children.dedup();
+        open_moves[index] = children.len() as u8;
+
+        if capture_win[index] != DTM_DRAW {
+            scheduled[capture_win[index] as usize - 1].push(index as u32);
+        } else if children.is_empty() && !has_draw[index] {
+            scheduled[capture_loss[index] as usize - 1].push(index as u32); // every move is a losing capture
+        }
+    }
+
+    let mut next = Vec::new();
+    let mut predecessors = Vec::with_capacity(64);
+
+    for plies in 0..=DTM_MAX_PLIES {
+        for index in std::mem::take(&mut scheduled[plies]) {
+            if values[index as usize] == DTM_DRAW {
+                values[index as usize] = plies as u8 + 1;
+                frontier.push(index);
+            }
+        }
+        if frontier.is_empty() && scheduled[plies..].iter().all(|s| s.is_empty()) {
+            break;
+        }
+        if plies == DTM_MAX_PLIES {
+            return Err(GenerateError::TooDeep(layout.name()));
+        }
+
+        for &index in &frontier {
+            let (white_to_move, squares) = decode(layout, index as usize);
+            let squares = &squares[..slots];
+            let board = board_from_squares(layout, white_to_move, squares);
+            let mover = board.turn.opponent(); // the side that made the last move
+            let empty = !board.occupied_bb;
+
+            predecessors.clear();
+            for (slot, &(piece_type, color)) in layout.pieces.iter().enumerate() {
+                if color != mover {
+                    continue;
+                }
+                let sq = Square::from_u8(squares[slot]);
+                let targets = match piece_type {
+                    PieceType::King => PRECOMPUTED.king_attacks[sq.to_u8() as usize],
+                    PieceType::Knight => PRECOMPUTED.knight_attacks[sq.to_u8() as usize],
+                    PieceType::Bishop => board.get_bishop_attacks(sq, board.occupied_bb),
+                    PieceType::Rook => board.get_rook_attacks(sq, board.occupied_bb),
+                    PieceType::Queen => board.get_rook_attacks(sq, board.occupied_bb) | board.get_bishop_attacks(sq, board.occupied_bb),
+                    PieceType::Pawn => unreachable!("DTM tables have no pawns"),
+                } & empty;
+
+                for from in targets.iter() {
+                    let mut previous = [0u8; DTM_MAX_PIECES];
+                    previous[..slots].copy_from_slice(squares);
+                    previous[slot] = from.to_u8();
+                    predecessors.push(layout.index(mover == Color::White, &mut previous[..slots]));
+                }
+            }
+            predecessors.sort_unstable();
+            predecessors.dedup();
+
+            for &previous in &predecessors {
+                if values[previous] != DTM_DRAW {
+                    continue; // already resolved or not a legal position
+                }
+                if plies % 2 == 0 {
+                    values[previous] = plies as u8 + 2;
+                    next.push(previous as u32);
+                } else {
+                    open_moves[previous] -= 1;
+                    if open_moves[previous] == 0 && !has_draw[previous] && capture_win[previous] == DTM_DRAW {
+                        let loss_plies = (plies + 1).max(capture_loss[previous].saturating_sub(1) as usize);
+                        if loss_plies == plies + 1 {
+                            values[previous] = plies as u8 + 2;
+                            next.push(previous as u32);
+                        } else {
+                            scheduled[loss_plies].push(previous as u32);
+                        }
+                    }
+                }
+            }
+        }
+
+        frontier.clear();
+        std::mem::swap(&mut frontier, &mut next);
+    }
+
+    // Illegal positions and mirror images are never probed, repeating the previous value makes the file smaller
+    for index in 0..size {
+        if values[index] == DTM_INVALID {
+            values[index] = if index == 0 { DTM_DRAW } else { values[index - 1] };
+        }
+    }
+    Ok(DtmTable { layout: layout.clone(), values })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(&fen.parse::<Fen>().unwrap()).unwrap()
+    }
+
+    fn generated(material: &str) -> DtmTables {
+        let mut tables = DtmTables::default();
+        generate(&mut tables, &DtmLayout::from_name(material).unwrap(), &mut |_| {}).unwrap();
+        tables
+    }
+
+    fn longest_win(table: &DtmTable) -> u8 {
+        table.values.iter().filter_map(|&value| match DtmResult::from_value(value) {
+            DtmResult::Win(plies) => Some(plies),
+            _ => None,
+        }).max().unwrap()
+    }
+
+    #[test]
+    fn kqk_longest_mate_is_ten_moves() {
+        let tables = generated("KQvK");
+        let table = &tables.tables[&DtmLayout::from_name("KQvK").unwrap().signature];
+        assert_eq!(longest_win(table), 19);
+        assert_eq!(longest_mate(table), 20);
+    }
+
+    #[test]
+    fn krk_longest_mate_is_sixteen_moves() {
+        let tables = generated("KRvK");
+        let table = &tables.tables[&DtmLayout::from_name("KRvK").unwrap().signature];
+        assert_eq!(longest_win(table), 31);
+        assert_eq!(longest_mate(table), 32);
+    }
+
+    #[test]
+    fn generated_tables_probe_known_positions() {
+        let tables = generated("KQvK");
+        assert_eq!(tables.probe(&board("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1")), Some(DtmResult::Win(1)));
+        assert_eq!(tables.probe(&board("6k1/6Q1/6K1/8/8/8/8/8 b - - 0 1")), Some(DtmResult::Loss(0)));
+        assert_eq!(tables.probe(&board("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1")), Some(DtmResult::Draw));
+        // the same positions with the colours swapped
+        assert_eq!(tables.probe(&board("1q6/8/8/8/8/6k1/8/7K b - - 0 1")), Some(DtmResult::Win(1)));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let tables = generated("KRvK");
+        let table = &tables.tables[&DtmLayout::from_name("KRvK").unwrap().signature];
+        let bytes = table_to_bytes(table);
+        assert!(bytes.len() < table.values.len());
+
+        let read = DtmTable::from_bytes(&bytes).unwrap();
+        assert_eq!(read.layout, table.layout);
+        assert_eq!(read.values, table.values);
+        assert!(DtmTable::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
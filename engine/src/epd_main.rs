@@ -0,0 +1,200 @@
+// EPD test suite runner (see src/chess_lib/epd.rs).
+//
+// Searches every position of one or more EPD files and checks the move found against the `bm` (best move)
+// and `am` (avoid move) operations, e.g. for suites like WAC or STS.
+//
+// usage: epd [--depth N] [--nodes N] [--time MS] FILE.epd...
+//
+// Each position is searched with iterative deepening until any of the limits is reached, `--time 0` means
+// no time limit. Positions without `bm` and `am` are searched but not counted.
+
+#[allow(dead_code, unused_imports)] // the tool only uses part of the engine
+mod chess_lib;
+use chess_lib::*;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+struct Config {
+    depth: u8,
+    nodes: Option<u64>,
+    time_ms: u64,
+    inputs: Vec<String>,
+}
+
+impl Config {
+    fn from_args() -> Result<Self, String> {
+        let mut config = Config {
+            depth: 32,
+            nodes: None,
+            time_ms: 1000,
+            inputs: Vec::new(),
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {}", name));
+            match arg.as_str() {
+                "--depth" => config.depth = parse_arg(&arg, &value(&arg)?)?,
+                "--nodes" => config.nodes = Some(parse_arg(&arg, &value(&arg)?)?),
+                "--time"  => config.time_ms = parse_arg(&arg, &value(&arg)?)?,
+                other if other.starts_with("--") => return Err(format!("unknown argument '{}'", other)),
+                input => config.inputs.push(input.to_string()),
+            }
+        }
+
+        if config.inputs.is_empty() {
+            return Err("no EPD files given".to_string());
+        }
+        if config.depth == 0 {
+            return Err("--depth must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, name))
+}
+
+
+struct SearchResult {
+    mv: ChessMove,
+    score: i32, // from White's perspective
+    depth: u8,
+    nodes: u64,
+}
+
+/// Iterative deepening within the limits. An iteration cut short is only used if no iteration finished.
+fn search(board: &Board, config: &Config) -> Option<SearchResult> {
+    let stop_signal = Arc::new(AtomicBool::new(false));
+    if config.time_ms > 0 {
+        // A fresh signal per position, so a late timer can not stop the next search
+        let stop_signal = Arc::clone(&stop_signal);
+        let time = Duration::from_millis(config.time_ms);
+        thread::spawn(move || {
+            thread::sleep(time);
+            stop_signal.store(true, Ordering::Relaxed);
+        });
+    }
+
+    let mut game = Game::new(board.clone(), config.depth, 3, stop_signal);
+    game.node_limit = config.nodes;
+
+    let mut best: Option<SearchResult> = None;
+    for depth in 1..=config.depth {
+        let result = game.find_best_move_with_score(depth);
+        let interrupted = game.should_stop();
+        if let Some((mv, score)) = result {
+            if !interrupted || best.is_none() {
+                best = Some(SearchResult { mv, score, depth, nodes: game.nodes });
+            }
+        }
+        if interrupted || result.is_none() {
+            break;
+        }
+    }
+    best
+}
+
+fn san(board: &Board, mv: &ChessMove) -> String {
+    board.to_san(mv, &mut Vec::new(), &mut Vec::new(), &mut HashMap::new())
+}
+
+fn main() {
+    let config = match Config::from_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("epd: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    init_kpk_bitbase();
+
+    let (mut solved, mut total, mut errors) = (0, 0, 0);
+    let started = Instant::now();
+
+    for input in &config.inputs {
+        let text = match std::fs::read_to_string(input) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("epd: could not read {}: {}", input, e);
+                std::process::exit(1);
+            }
+        };
+
+        for (line_number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let location = format!("{}:{}", input, line_number + 1);
+
+            let record = match line.parse::<EpdRecord>() {
+                Ok(record) => record,
+                Err(e) => {
+                    println!("{}: {}", location, e);
+                    errors += 1;
+                    continue;
+                }
+            };
+            let (best_moves, avoid_moves) = match (record.best_moves(), record.avoid_moves()) {
+                (Ok(bm), Ok(am)) => (bm, am),
+                (Err(e), _) | (_, Err(e)) => {
+                    println!("{}: {}", location, e);
+                    errors += 1;
+                    continue;
+                }
+            };
+            let id = record.id().map(str::to_string).unwrap_or(location);
+
+            let position_started = Instant::now();
+            let Some(result) = search(&record.board, &config) else {
+                println!("{}: no legal moves", id);
+                continue;
+            };
+            let elapsed = position_started.elapsed();
+
+            let side_score = if record.board.turn == Color::White { result.score } else { -result.score };
+            let score = match mate_distance(result.score, result.depth) {
+                Some(moves) => format!("mate {}", if record.board.turn == Color::White { moves } else { -moves }),
+                None if side_score >= TB_WIN_SCORE => "tablebase win".to_string(),
+                None if side_score <= -TB_WIN_SCORE => "tablebase loss".to_string(),
+                None => format!("cp {}", side_score),
+            };
+
+            let found = san(&record.board, &result.mv);
+            let checked = !best_moves.is_empty() || !avoid_moves.is_empty();
+            let ok = (best_moves.is_empty() || best_moves.contains(&result.mv)) && !avoid_moves.contains(&result.mv);
+
+            let mut expected = String::new();
+            for (opcode, moves) in [("bm", &best_moves), ("am", &avoid_moves)] {
+                if !moves.is_empty() {
+                    let moves: Vec<String> = moves.iter().map(|mv| san(&record.board, mv)).collect();
+                    expected.push_str(&format!(" {} {}", opcode, moves.join(" ")));
+                }
+            }
+
+            let status = match (checked, ok) {
+                (false, _) => "    ",
+                (true, true) => "ok  ",
+                (true, false) => "FAIL",
+            };
+            println!("{} {}: {} ({}, depth {}, {} nodes, {} ms){}",
+                     status, id, found, score, result.depth, result.nodes, elapsed.as_millis(), expected);
+
+            if checked {
+                total += 1;
+                if ok {
+                    solved += 1;
+                }
+            }
+        }
+    }
+
+    println!("solved {} of {} ({} unreadable) in {:.1}s", solved, total, errors, started.elapsed().as_secs_f32());
+}
@@ -26,12 +26,27 @@ fn main() {
     #[cfg(feature = "tracy")]
     tracing::event!(tracing::Level::INFO, "STARTING PROFILING");
 
+    // `native_engine bench [depth]` prints the bench signature and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("bench") {
+        let depth = args.get(1).and_then(|d| d.parse().ok()).filter(|&d| d != 0).unwrap_or(BENCH_DEPTH);
+        run_bench(depth);
+        return;
+    }
+
+    // The first command selects the protocol: `xboard` for CECP (WinBoard), anything else is UCI
     let mut engine = Engine::new();
+    let mut xboard: Option<bool> = None;
     let stdin = io::stdin();
     for line in stdin.lock().lines() {
         let line = line.unwrap_or_default();
-        if let Ok(command) = parse_command(&line) {
-            engine.process_command(command);
+        if xboard.is_none() && !line.trim().is_empty() {
+            xboard = Some(line.trim() == "xboard");
+        }
+        if xboard == Some(true) {
+            engine.process_xboard_line(&line);
+        } else {
+            engine.process_line(&line);
         }
     }
 
@@ -0,0 +1,80 @@
+// Distance-to-mate table generator (see src/chess_lib/dtm.rs).
+//
+// usage: tbgen [--out DIR] [MATERIAL...]
+//
+// MATERIAL is e.g. KQvK or KQvKR, without any all pawnless 3 and 4 piece tables are generated.
+// Tables needed for captures are generated as well, every table ends up in DIR/<material>.cftb.
+
+#[allow(dead_code, unused_imports)] // the tool only uses part of the engine
+mod chess_lib;
+use chess_lib::*;
+
+mod dtm_generator;
+use dtm_generator::*;
+
+use std::path::PathBuf;
+use std::time::Instant;
+
+const PIECES: [char; 4] = ['Q', 'R', 'B', 'N'];
+
+fn all_materials() -> Vec<String> {
+    let mut materials = Vec::new();
+    for (i, a) in PIECES.iter().enumerate() {
+        materials.push(format!("K{}vK", a));
+        for b in &PIECES[i..] {
+            materials.push(format!("K{}{}vK", a, b));
+            materials.push(format!("K{}vK{}", a, b));
+        }
+    }
+    materials
+}
+
+fn main() {
+    let mut out = PathBuf::from(".");
+    let mut materials = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = PathBuf::from(args.next().unwrap_or_else(|| {
+                eprintln!("tbgen: missing value for --out");
+                std::process::exit(1);
+            })),
+            material => materials.push(material.to_string()),
+        }
+    }
+    if materials.is_empty() {
+        materials = all_materials();
+    }
+
+    let layouts: Vec<DtmLayout> = materials.iter().map(|m| DtmLayout::from_name(m).unwrap_or_else(|e| {
+        eprintln!("tbgen: {}", e);
+        std::process::exit(1);
+    })).collect();
+
+    if let Err(e) = std::fs::create_dir_all(&out) {
+        eprintln!("tbgen: could not create {}: {}", out.display(), e);
+        std::process::exit(1);
+    }
+
+    let mut tables = DtmTables::default();
+    let mut started = Instant::now();
+    let mut save = |table: &DtmTable| {
+        let path = out.join(format!("{}.cftb", table.layout.name()));
+        let bytes = table_to_bytes(table);
+        if let Err(e) = std::fs::write(&path, &bytes) {
+            eprintln!("tbgen: could not write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        eprintln!("tbgen: {} in {:.1}s, longest mate {} plies, {} bytes -> {}",
+                  table.layout.name(), started.elapsed().as_secs_f32(), longest_mate(table), bytes.len(), path.display());
+        started = Instant::now();
+    };
+
+    for layout in &layouts {
+        if let Err(e) = generate(&mut tables, layout, &mut save) {
+            eprintln!("tbgen: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -3,7 +3,8 @@ use chess_lib::*;
 
 use wasm_bindgen::prelude::*;
 use web_sys::console;
-use std::sync::{atomic::AtomicUsize, Arc, Mutex};
+use std::sync::{atomic::{AtomicBool, AtomicUsize}, Arc, Mutex};
+use std::collections::HashMap;
 use wasm_bindgen_spawn::ThreadCreator;
 
 use std::sync::{atomic::Ordering};
@@ -168,9 +169,200 @@ pub fn start_wasm_engine() {
 pub fn send_uci_message(msg: &str) {
     let engine_mutex = MUTEXED_ENGINE.get().unwrap();
     let mut engine_guard = engine_mutex.lock().unwrap();
-    if let Ok(command) = parse_command(msg) {
-        engine_guard.process_command(command);
-    } else {
-        log!("PARSE FAIL: {}", msg);
+    engine_guard.process_line(msg);
+}
+
+
+#[wasm_bindgen]
+pub fn load_eval_params(bytes: &[u8]) -> Result<(), JsValue> {
+    let params = EvalParams::from_bytes(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    set_eval_params(params);
+    log!("Loaded evaluation parameters ({} bytes)", bytes.len());
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn load_nnue_network(bytes: &[u8]) -> Result<(), JsValue> {
+    let network = Network::from_bytes(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    log!("Loaded NNUE network {:?}", network);
+    set_nnue_network(Some(network));
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn load_dtm_table(bytes: &[u8]) -> Result<(), JsValue> {
+    let table = DtmTable::from_bytes(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    log!("Loaded DTM table {:?}", table);
+    let mut tables = (*dtm_tables()).clone();
+    tables.insert(table);
+    set_dtm_tables(tables);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn load_opening_book(bytes: &[u8]) -> Result<(), JsValue> {
+    let book = PolyglotBook::from_bytes(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    log!("Loaded opening book {:?}", book);
+    set_opening_book(Some(book));
+    Ok(())
+}
+
+
+/// Game tree for the web analysis board, nodes are addressed by the ids returned from `play`/`play_san`.
+#[wasm_bindgen]
+pub struct AnalysisBoard {
+    tree: GameTree,
+}
+
+#[wasm_bindgen]
+impl AnalysisBoard {
+    /// Starts from `fen`, or the standard position if none is given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(fen: Option<String>) -> Result<AnalysisBoard, JsValue> {
+        let board = match fen {
+            Some(fen) => {
+                let fen = fen.parse::<Fen>().map_err(|e| JsValue::from_str(&e.to_string()))?;
+                Board::from_fen(&fen).map_err(|e| JsValue::from_str(&e.to_string()))?
+            }
+            None => Board::new_start_pos(),
+        };
+        Ok(AnalysisBoard { tree: GameTree::new(board) })
+    }
+
+    /// Reads the first game of `pgn` with all its variations and comments.
+    pub fn from_pgn(pgn: &str) -> Result<AnalysisBoard, JsValue> {
+        let game = PgnGame::parse(pgn).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(AnalysisBoard { tree: GameTree::from_pgn(&game) })
+    }
+
+    /// Plays a move in UCI notation from the current node and returns the id of the new current node.
+    pub fn play(&mut self, uci: &str) -> Result<usize, JsValue> {
+        let mv = parse_move_string(uci).map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
+        self.tree.play(&mv).ok_or_else(|| JsValue::from_str(&format!("Illegal move: {}", uci)))
+    }
+
+    pub fn play_san(&mut self, san: &str) -> Result<usize, JsValue> {
+        self.tree.play_san(san).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn forward(&mut self) -> bool {
+        self.tree.forward()
+    }
+
+    pub fn back(&mut self) -> bool {
+        self.tree.back()
+    }
+
+    pub fn go_to(&mut self, id: usize) -> bool {
+        self.tree.go_to(id)
+    }
+
+    pub fn go_to_start(&mut self) {
+        self.tree.go_to_start()
+    }
+
+    pub fn go_to_end(&mut self) {
+        self.tree.go_to_end()
+    }
+
+    pub fn promote_variation(&mut self, id: usize) -> bool {
+        self.tree.promote_variation(id)
+    }
+
+    pub fn make_main_line(&mut self, id: usize) -> bool {
+        self.tree.make_main_line(id)
+    }
+
+    pub fn delete_node(&mut self, id: usize) -> bool {
+        self.tree.delete(id)
+    }
+
+    /// Replaces the comment of a node, `[%eval]`/`[%clk]` commands in it are kept as annotations.
+    pub fn set_comment(&mut self, id: usize, comment: &str) -> bool {
+        match self.tree.node_mut(id) {
+            Some(node) => {
+                node.annotation = MoveAnnotation::from_comment(comment);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_nags(&mut self, id: usize, nags: Vec<u8>) -> bool {
+        match self.tree.node_mut(id) {
+            Some(node) => {
+                node.nags = nags;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        self.tree.pgn_tags.set(name, value);
+    }
+
+    pub fn current(&self) -> usize {
+        self.tree.current()
+    }
+
+    pub fn fen(&self) -> String {
+        self.tree.board().to_fen()
+    }
+
+    pub fn to_pgn(&self) -> String {
+        self.tree.to_pgn()
+    }
+
+    /// Searches the current position to `depth` and returns the best `multi_pv` lines as JSON, best first:
+    /// `[{ multipv, score_cp, mate, pv, pv_san }]` with the score from the side to move's point of view, `mate` in moves
+    /// (negative if the side to move gets mated, null if there is no mate) and `pv` in UCI.
+    /// Runs on the calling thread, so call it from a worker.
+    pub fn analyse(&self, depth: u8, multi_pv: u8) -> Result<String, JsValue> {
+        if depth == 0 || multi_pv == 0 {
+            return Err(JsValue::from_str("depth and multi_pv must be at least 1"));
+        }
+        let mut game = self.tree.to_game(depth, 3, Arc::new(AtomicBool::new(false)));
+        let lines = game.find_best_lines(depth, multi_pv as usize);
+
+        let lines: Vec<_> = lines.iter().enumerate().map(|(k, line)| {
+            let mut board = game.board.clone();
+            let mut pv_san = Vec::with_capacity(line.moves.len());
+            for mv in &line.moves {
+                pv_san.push(board.to_san(mv, &mut Vec::new(), &mut Vec::new(), &mut HashMap::new()));
+                board = board.make_move(mv);
+            }
+            serde_json::json!({
+                "multipv": k + 1,
+                "score_cp": uci_score_cp(&game.board, line.score),
+                "mate": uci_mate_distance(&game.board, line.score, depth),
+                "pv": line.moves.iter().map(|mv| mv.to_uci()).collect::<Vec<_>>(),
+                "pv_san": pv_san,
+            })
+        }).collect();
+        Ok(serde_json::Value::Array(lines).to_string())
+    }
+
+    /// Every node reachable from the root as JSON:
+    /// `[{ id, parent, uci, san, fen, comment_before, comment, nags, children }]`, the root first, the first child continuing the line.
+    pub fn nodes_json(&self) -> String {
+        let mut nodes = Vec::new();
+        let mut stack = vec![GameTree::ROOT];
+        while let Some(id) = stack.pop() {
+            let node = self.tree.node(id).unwrap();
+            nodes.push(serde_json::json!({
+                "id": id,
+                "parent": node.parent,
+                "uci": node.mv.map(|mv| mv.to_uci()),
+                "san": node.san,
+                "fen": node.board.to_fen(),
+                "comment_before": node.comment_before,
+                "comment": node.annotation.to_comment(),
+                "nags": node.nags,
+                "children": node.children,
+            }));
+            stack.extend(node.children.iter().rev());
+        }
+        serde_json::Value::Array(nodes).to_string()
     }
 }